@@ -0,0 +1,93 @@
+use async_spdk::{bdev_batch::*, event::app_stop, *};
+use bdev::*;
+use futures_lite::{future, stream, StreamExt};
+use log::*;
+
+/// `bdev_io_pool_size` in hello_batch.json, on a single thread
+const BDEV_IO_POOL_SIZE: u64 = 64;
+
+fn main() {
+    env_logger::init();
+    event::AppOpts::new()
+        .name("hello_batch")
+        .config_file(&std::env::args().nth(1).expect("no config_file"))
+        .block_on(async_main())
+        .unwrap();
+}
+
+async fn async_main() -> Result<()> {
+    info!("Start main: hello_batch");
+
+    let bdev_desc = BdevDesc::create_desc("Malloc0")?;
+    let channel = bdev_desc.get_io_channel()?;
+    let bdev = bdev_desc.get_bdev()?;
+    let blk_size = bdev.get_block_size() as usize;
+    let align = bdev.get_buf_align();
+
+    // write 1024 blocks, 16 at a time, each filled with its own number
+    let writes = stream::iter((0..1024u64).map(|i| {
+        let mut buf = env::DmaBuf::alloc(blk_size, align);
+        buf.as_mut().fill(i as u8);
+        BatchIo::Write {
+            offset_blocks: i,
+            buf,
+        }
+    }));
+    let mut batch = BatchSubmitter::new(&bdev_desc, &channel, 16, writes);
+    while let Some((_, ret)) = batch.next().await {
+        ret?;
+    }
+    info!("Finish writing");
+
+    let reads = stream::iter((0..1024u64).map(|i| BatchIo::Read {
+        offset_blocks: i,
+        buf: env::DmaBuf::alloc(blk_size, align),
+    }));
+    let mut batch = BatchSubmitter::new(&bdev_desc, &channel, 16, reads);
+    let mut mismatches = 0;
+    while let Some((io, ret)) = batch.next().await {
+        ret?;
+        if let BatchIo::Read { offset_blocks, buf } = io {
+            if buf.as_ref().iter().any(|&b| b != offset_blocks as u8) {
+                mismatches += 1;
+            }
+        }
+    }
+    if mismatches != 0 {
+        error!("Inconsistent data in {} blocks!", mismatches);
+    } else {
+        info!("Data matches!");
+    }
+
+    // hold every spdk_bdev_io with borrowed reads, so that the batch starts with
+    // nothing in flight and has to wait for a free spdk_bdev_io
+    let mut guards = vec![];
+    for i in 0..BDEV_IO_POOL_SIZE {
+        guards.push(bdev_desc.read_blocks_borrowed(&channel, i, 1).await?);
+    }
+    let reads = stream::iter((0..64u64).map(|i| BatchIo::Read {
+        offset_blocks: i,
+        buf: env::DmaBuf::alloc(blk_size, align),
+    }));
+    let mut batch = BatchSubmitter::new(&bdev_desc, &channel, 16, reads);
+    let (completed, ()) = future::zip(
+        async {
+            let mut completed = 0;
+            while let Some((_, ret)) = batch.next().await {
+                ret?;
+                completed += 1;
+            }
+            Ok::<_, SpdkError>(completed)
+        },
+        // polled after the batch is out of spdk_bdev_io
+        async { drop(guards) },
+    )
+    .await;
+    info!("Read {} blocks after waiting for spdk_bdev_io", completed?);
+    drop(channel);
+    bdev_desc.close();
+
+    app_stop();
+
+    Ok(())
+}
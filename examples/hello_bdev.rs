@@ -1,5 +1,6 @@
 use async_spdk::{event::app_stop, *};
 use bdev::*;
+use futures_lite::StreamExt;
 use log::*;
 
 fn main() {
@@ -14,6 +15,10 @@ fn main() {
 async fn async_main() -> Result<()> {
     info!("Start main: hello_bdev");
 
+    for bdev in BDev::iter() {
+        info!("Found bdev: {:?}", bdev.info());
+    }
+
     let bdev_desc = BdevDesc::create_desc("Malloc0")?;
     info!("Get bdev descriptor");
 
@@ -32,8 +37,25 @@ async fn async_main() -> Result<()> {
     let channel = bdev_desc.get_io_channel()?;
     info!("IO channel get");
 
+    let mut identify_buf = DmaBuf::new(NvmeCmd::IDENTIFY_DATA_SIZE as u64, 0x1000)?;
+    match bdev_desc
+        .nvme_admin_passthru(&channel, &NvmeCmd::identify_controller(), &mut identify_buf)
+        .await
+    {
+        Err(SpdkError::Unsupported(io_type)) => info!("{:?} is not supported", io_type),
+        ret => info!("Identify controller: {:?}", ret),
+    }
+
+    match bdev_desc
+        .write_blocks(&channel, Bdev.num_blocks(), write_buf.as_ref())
+        .await
+    {
+        Err(SpdkError::InvalidIo(msg)) => info!("Rejected write: {}", msg),
+        ret => error!("Write past the end: {:?}", ret),
+    }
+
     bdev_desc
-        .write(&channel, 0, blk_size as u64, write_buf.as_ref())
+        .write_blocks(&channel, 0, write_buf.as_ref())
         .await?;
 
     info!("Finish writing");
@@ -41,7 +63,7 @@ async fn async_main() -> Result<()> {
     let mut read_buf = env::DmaBuf::alloc(blk_size as usize, 0x1000);
 
     bdev_desc
-        .read(&channel, 0, blk_size as u64, read_buf.as_mut())
+        .read_blocks(&channel, 0, read_buf.as_mut())
         .await?;
     info!("Finish reading");
 
@@ -51,13 +73,43 @@ async fn async_main() -> Result<()> {
         info!("Data matches!");
     }
 
+    // copied by the bdev, or through a buffer if it can't copy by itself
+    bdev_desc.copy_blocks(&channel, 1, 0, 1).await?;
+    bdev_desc
+        .read_blocks(&channel, 1, read_buf.as_mut())
+        .await?;
+    if write_buf.as_ref() != read_buf.as_ref() {
+        error!("Inconsistent copied data!");
+    } else {
+        info!("Copied data matches!");
+    }
+
+    // malloc bdevs don't track allocation, so the whole bdev is one extent
+    {
+        let extents = bdev_desc.extents(&channel);
+        futures_lite::pin!(extents);
+        while let Some(extent) = extents.next().await {
+            info!("Allocated blocks: {:?}", extent?);
+        }
+    }
+
+    // let the bdev layer supply the buffer, it is released with the guard
+    let guard = bdev_desc.read_blocks_borrowed(&channel, 0, 1).await?;
+    if guard.buffers().concat() != write_buf.as_ref() {
+        error!("Inconsistent borrowed data!");
+    } else {
+        info!("Borrowed data matches!");
+    }
+    drop(guard);
+
+    // the channel borrows the descriptor, release it before closing
+    drop(channel);
     bdev_desc.close();
     info!("Bdev closed");
 
-    // attention! io channel and dma buffer is dropped automatically
+    // attention! dma buffers are dropped automatically
     // since we implement drop trait
     // don't need to call any free API
-    // TODO: any other struct need to implement DROP for more convenience?
 
     app_stop();
 
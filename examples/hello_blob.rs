@@ -84,8 +84,7 @@ async fn async_main() -> Result<()> {
     blobstore.delete_blob(blob_id).await?;
     info!("Deleted");
 
-    // XXX: io_channel must be dropped before unload.
-    // TODO: find a way to force that in Rust
+    // the io_channel borrows the blobstore, drop it before unload
     drop(channel);
     blobstore.unload().await?;
     info!("Blobstore unloaded");
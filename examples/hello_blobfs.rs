@@ -109,7 +109,7 @@ async fn async_main(
     *shutdown_poller.lock().unwrap() = Poller::register(move || {
         if *shutdown_copy.lock().unwrap() == true {
             info!("shutdonw poller receive shutdown signal");
-            shutdown_fs.lock().unwrap().unload_sync();
+            std::mem::take(&mut *shutdown_fs.lock().unwrap()).unload_sync();
             shutdown_poller_copy.lock().unwrap().unregister();
             app_stop();
         }
@@ -0,0 +1,67 @@
+use async_spdk::{bdev_builtin::*, event::app_stop, *};
+use bdev::*;
+use log::*;
+
+fn main() {
+    env_logger::init();
+    event::AppOpts::new()
+        .name("hello_create")
+        .config_file(&std::env::args().nth(1).expect("no config_file"))
+        .block_on(async_main())
+        .unwrap();
+}
+
+async fn async_main() -> Result<()> {
+    info!("Start main: hello_create");
+
+    // Malloc0 + Malloc1 -> Raid0 -> Raid0p0, Raid0p1 -> Delay0
+    MallocOpts::new("Malloc0", 16384, 512).create().await?;
+    MallocOpts::new("Malloc1", 16384, 512).create().await?;
+    let raid = RaidOpts::new("Raid0", RaidLevel::Raid0)
+        .strip_size_kb(64)
+        .base_bdev("Malloc0")
+        .base_bdev("Malloc1")
+        .create()
+        .await?;
+    info!("Created RAID: {:?}", raid.info());
+    let parts = create_split("Raid0", 2, 0).await?;
+    let latencies = DelayLatencies {
+        avg_read: 100,
+        p99_read: 200,
+        avg_write: 100,
+        p99_write: 200,
+    };
+    let delay = create_delay(&parts[0].name(), "Delay0", latencies).await?;
+    info!("Created delay bdev: {:?}", delay.info());
+
+    let bdev_desc = BdevDesc::create_desc("Delay0")?;
+    let channel = bdev_desc.get_io_channel()?;
+    let blk_size = delay.get_block_size() as usize;
+    let mut write_buf = env::DmaBuf::alloc(blk_size * 256, 0x1000);
+    write_buf.as_mut().fill(0x5a);
+    bdev_desc
+        .write_blocks(&channel, 0, write_buf.as_ref())
+        .await?;
+    let mut read_buf = env::DmaBuf::alloc(blk_size * 256, 0x1000);
+    bdev_desc
+        .read_blocks(&channel, 0, read_buf.as_mut())
+        .await?;
+    if write_buf.as_ref() != read_buf.as_ref() {
+        error!("Inconsistent data!");
+    } else {
+        info!("Data matches!");
+    }
+    drop(channel);
+    bdev_desc.close();
+
+    delete_delay("Delay0").await?;
+    delete_split("Raid0").await?;
+    delete_raid("Raid0").await?;
+    delete_malloc("Malloc1").await?;
+    delete_malloc("Malloc0").await?;
+    info!("Deleted all bdevs");
+
+    app_stop();
+
+    Ok(())
+}
@@ -0,0 +1,101 @@
+use async_spdk::{event::app_stop, *};
+use bdev::*;
+use dif::*;
+use log::*;
+
+const NUM_BLOCKS: usize = 4;
+
+fn main() {
+    env_logger::init();
+    event::AppOpts::new()
+        .name("hello_dif")
+        .config_file(&std::env::args().nth(1).expect("no config_file"))
+        .block_on(async_main())
+        .unwrap();
+}
+
+async fn async_main() -> Result<()> {
+    info!("Start main: hello_dif");
+
+    let bdev_desc = BdevDesc::create_desc("Malloc0")?;
+    let bdev = bdev_desc.get_bdev()?;
+    info!(
+        "Block size: {}, metadata size: {}, DIF type: {:?}, separate metadata: {}",
+        bdev.get_block_size(),
+        bdev.md_size(),
+        bdev.dif_type(),
+        bdev.is_md_separate()
+    );
+    assert!(bdev.is_md_separate());
+
+    let blk_size = bdev.get_block_size() as usize;
+    let md_size = bdev.md_size() as usize;
+    let channel = bdev_desc.get_io_channel()?;
+
+    // the reference tag of type 1 DIF is the LBA
+    let ctx = DifOpts::from_bdev(&bdev).init_ref_tag(0).build()?;
+
+    let mut write_buf = env::DmaBuf::alloc(blk_size * NUM_BLOCKS, 0x1000);
+    write_buf.as_mut().fill(0x5a);
+    let mut write_md = env::DmaBuf::alloc_zeroed(md_size * NUM_BLOCKS, 0x1000);
+    ctx.dix_generate(write_buf.as_ref(), write_md.as_mut())?;
+    info!("Protection information generated");
+
+    bdev_desc
+        .write_blocks_with_md(
+            &channel,
+            0,
+            NUM_BLOCKS as u64,
+            write_buf.as_ref(),
+            write_md.as_ref(),
+        )
+        .await?;
+    info!("Finish writing");
+
+    let mut read_buf = env::DmaBuf::alloc(blk_size * NUM_BLOCKS, 0x1000);
+    let mut read_md = env::DmaBuf::alloc(md_size * NUM_BLOCKS, 0x1000);
+    bdev_desc
+        .read_blocks_with_md(
+            &channel,
+            0,
+            NUM_BLOCKS as u64,
+            read_buf.as_mut(),
+            read_md.as_mut(),
+        )
+        .await?;
+    ctx.dix_verify(read_buf.as_ref(), read_md.as_ref())?;
+    if write_buf.as_ref() != read_buf.as_ref() {
+        error!("Inconsistent data!");
+    } else {
+        info!("Data and protection information match!");
+    }
+
+    // corrupt the data so that the guard no longer matches
+    write_buf.as_mut()[0] ^= 0xff;
+    match ctx.dix_verify(write_buf.as_ref(), write_md.as_ref()) {
+        Err(SpdkError::Dif(err)) => {
+            assert_eq!(err.kind, DifErrorKind::Guard);
+            info!("Corruption detected: {:?}", err);
+        }
+        ret => error!("Corruption not detected: {:?}", ret),
+    }
+    match bdev_desc
+        .write_blocks_with_md(
+            &channel,
+            0,
+            NUM_BLOCKS as u64,
+            write_buf.as_ref(),
+            write_md.as_ref(),
+        )
+        .await
+    {
+        Err(err) => info!("Corrupted write rejected: {}", err),
+        Ok(()) => warn!("Corrupted write accepted by the bdev"),
+    }
+
+    drop(channel);
+    bdev_desc.close();
+    app_stop();
+
+    Ok(())
+}
@@ -0,0 +1,101 @@
+use async_spdk::{bdev_builtin::*, event::app_stop, *};
+use bdev::*;
+use log::*;
+
+fn main() {
+    env_logger::init();
+    event::AppOpts::new()
+        .name("hello_error")
+        .config_file(&std::env::args().nth(1).expect("no config_file"))
+        .block_on(async_main())
+        .unwrap();
+}
+
+async fn async_main() -> Result<()> {
+    info!("Start main: hello_error");
+
+    MallocOpts::new("Malloc0", 32768, 512).create().await?;
+    let bdev = create_error("Malloc0").await?;
+    let name = bdev.name();
+    info!("Created error bdev: {}", name);
+
+    let blk_size = bdev.get_block_size() as usize;
+    let bdev_desc = BdevDesc::create_desc(&name)?;
+    let channel = bdev_desc.get_io_channel()?;
+    let mut write_buf = env::DmaBuf::alloc(blk_size, 0x1000);
+    write_buf.as_mut().fill(0x5a);
+    let mut read_buf = env::DmaBuf::alloc(blk_size, 0x1000);
+
+    // the next write fails, then writes succeed again
+    inject_error(&name, Some(IoType::Write), InjectedError::Failure, 1)?;
+    let ret = bdev_desc
+        .write_blocks(&channel, 0, write_buf.as_ref())
+        .await;
+    info!("Write with injected failure: {:?}", ret);
+    assert!(matches!(ret, Err(SpdkError::IoFailed(_))));
+    bdev_desc
+        .write_blocks(&channel, 0, write_buf.as_ref())
+        .await?;
+
+    // the byte-addressed write fails the same way
+    inject_error(&name, Some(IoType::Write), InjectedError::Failure, 1)?;
+    let ret = bdev_desc
+        .write(&channel, 0, blk_size as u64, write_buf.as_ref())
+        .await;
+    info!("Byte write with injected failure: {:?}", ret);
+    assert!(matches!(ret, Err(SpdkError::IoFailed(_))));
+    bdev_desc
+        .write(&channel, 0, blk_size as u64, write_buf.as_ref())
+        .await?;
+
+    // NOMEM is retried by the bdev layer
+    inject_error(&name, Some(IoType::Write), InjectedError::Nomem, 1)?;
+    bdev_desc
+        .write_blocks(&channel, 0, write_buf.as_ref())
+        .await?;
+    info!("Write with injected NOMEM succeeded");
+
+    let corrupt = InjectedError::CorruptData {
+        offset: 0,
+        value: 0xff,
+    };
+    inject_error(&name, Some(IoType::Read), corrupt, 1)?;
+    bdev_desc
+        .read_blocks(&channel, 0, read_buf.as_mut())
+        .await?;
+    assert_eq!(read_buf.as_ref()[0], 0x5a ^ 0xff);
+    info!("Read corrupted data: {:#x}", read_buf.as_ref()[0]);
+
+    drop(channel);
+    bdev_desc.close();
+
+    // errors surface through the blobstore as well
+    let mut bs_dev = blob_bdev::BlobStoreBDev::create(&name)?;
+    let blobstore = blob::Blobstore::init(&mut bs_dev).await?;
+    let blob_id = blobstore.create_blob().await?;
+    let blob = blobstore.open_blob(blob_id).await?;
+    blob.resize(1).await?;
+    blob.sync_metadata().await?;
+    let channel = blobstore.alloc_io_channel()?;
+    let mut write_buf = env::DmaBuf::alloc(blobstore.io_unit_size() as usize, 0x1000);
+    write_buf.as_mut().fill(0x5a);
+
+    inject_error(&name, None, InjectedError::Failure, 1)?;
+    let ret = blob.write(&channel, 0, write_buf.as_ref()).await;
+    info!("Blob write with injected failure: {:?}", ret);
+    assert!(matches!(ret, Err(SpdkError::Errno { errno, .. }) if errno == -(spdk_sys::EIO as i32)));
+    clear_errors(&name)?;
+    blob.write(&channel, 0, write_buf.as_ref()).await?;
+
+    blob.close().await?;
+    blobstore.delete_blob(blob_id).await?;
+    drop(channel);
+    blobstore.unload().await?;
+
+    delete_error(&name).await?;
+    delete_malloc("Malloc0").await?;
+
+    app_stop();
+
+    Ok(())
+}
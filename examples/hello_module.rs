@@ -0,0 +1,100 @@
+use async_spdk::{bdev_module::*, event::app_stop, *};
+use bdev::*;
+use log::*;
+use std::sync::Mutex;
+
+const BLOCK_SIZE: u32 = 512;
+const NUM_BLOCKS: u64 = 1024;
+
+/// A bdev keeping its data in memory, shared by the channels of all threads.
+struct RamDisk {
+    data: Mutex<Vec<u8>>,
+}
+
+impl BdevModule for RamDisk {
+    const NAME: &'static str = "ram_disk";
+    type Channel = ();
+
+    fn io_type_supported(&self, io_type: IoType) -> bool {
+        matches!(io_type, IoType::Read | IoType::Write | IoType::Flush)
+    }
+
+    fn create_channel(&self) -> Self::Channel {}
+
+    fn submit_request(&self, _channel: &Self::Channel, mut io: BdevIo) {
+        let offset = (io.offset_blocks() * BLOCK_SIZE as u64) as usize;
+        let len = (io.num_blocks() * BLOCK_SIZE as u64) as usize;
+        match io.io_type() {
+            // the lock is released before completing, which may submit another I/O
+            Some(IoType::Read) => {
+                io.copy_from(&self.data.lock().unwrap()[offset..offset + len]);
+                io.complete(Ok(()));
+            }
+            Some(IoType::Write) => {
+                io.copy_to(&mut self.data.lock().unwrap()[offset..offset + len]);
+                io.complete(Ok(()));
+            }
+            // complete asynchronously, as a real device would
+            Some(IoType::Flush) => {
+                event::spawn(async move { io.complete(Ok(())) });
+            }
+            // dropping the I/O fails it
+            _ => {}
+        }
+    }
+
+    fn dump_info_json(&self, w: &mut JsonWriter) {
+        w.named_u64("size", self.data.lock().unwrap().len() as u64);
+    }
+}
+
+fn main() {
+    env_logger::init();
+    event::AppOpts::new()
+        .name("hello_module")
+        .config_file(&std::env::args().nth(1).expect("no config_file"))
+        .block_on(async_main())
+        .unwrap();
+}
+
+async fn async_main() -> Result<()> {
+    info!("Start main: hello_module");
+
+    let ram_disk = RamDisk {
+        data: Mutex::new(vec![0; (BLOCK_SIZE as u64 * NUM_BLOCKS) as usize]),
+    };
+    let bdev = bdev_module::register("Ram0", BLOCK_SIZE, NUM_BLOCKS, ram_disk)?;
+    info!("Registered bdev: {:?}", bdev.info());
+
+    let bdev_desc = BdevDesc::create_desc("Ram0")?;
+    let channel = bdev_desc.get_io_channel()?;
+
+    let mut write_buf = env::DmaBuf::alloc(BLOCK_SIZE as usize * 4, 0x1000);
+    write_buf.as_mut().fill(0x5a);
+    bdev_desc
+        .write_blocks(&channel, 8, write_buf.as_ref())
+        .await?;
+    bdev_desc
+        .flush(&channel, 0, BLOCK_SIZE as u64 * NUM_BLOCKS)
+        .await?;
+    info!("Finish writing");
+
+    let mut read_buf = env::DmaBuf::alloc(BLOCK_SIZE as usize * 4, 0x1000);
+    bdev_desc
+        .read_blocks(&channel, 8, read_buf.as_mut())
+        .await?;
+    if write_buf.as_ref() != read_buf.as_ref() {
+        error!("Inconsistent data!");
+    } else {
+        info!("Data matches!");
+    }
+
+    drop(channel);
+    bdev_desc.close();
+    bdev_module::unregister::<RamDisk>("Ram0").await?;
+    info!("Bdev unregistered");
+
+    app_stop();
+
+    Ok(())
+}
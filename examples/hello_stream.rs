@@ -0,0 +1,51 @@
+use async_spdk::{bdev_stream::BdevStream, event::app_stop, *};
+use bdev::*;
+use log::*;
+use std::io::SeekFrom;
+use tokio::io::{AsyncReadExt, AsyncSeekExt, AsyncWriteExt};
+
+fn main() {
+    env_logger::init();
+    event::AppOpts::new()
+        .name("hello_stream")
+        .config_file(&std::env::args().nth(1).expect("no config_file"))
+        .block_on(async_main())
+        .unwrap();
+}
+
+async fn async_main() -> Result<()> {
+    info!("Start main: hello_stream");
+
+    let bdev_desc = BdevDesc::create_desc("Malloc0")?;
+    let channel = bdev_desc.get_io_channel()?;
+    let mut stream = BdevStream::new(&bdev_desc, &channel)?;
+    copy_and_verify(&mut stream)
+        .await
+        .expect("bdev stream I/O failed");
+    drop(stream);
+    drop(channel);
+    bdev_desc.close();
+
+    app_stop();
+
+    Ok(())
+}
+
+async fn copy_and_verify(stream: &mut BdevStream<'_>) -> std::io::Result<()> {
+    // neither the offset nor the length is aligned to blocks
+    let data: Vec<u8> = (0..10000).map(|i| i as u8).collect();
+    stream.seek(SeekFrom::Start(100)).await?;
+    let copied = tokio::io::copy(&mut data.as_slice(), stream).await?;
+    stream.flush().await?;
+    info!("Copied {} bytes to the bdev", copied);
+
+    let mut read = vec![0; data.len()];
+    stream.seek(SeekFrom::Start(100)).await?;
+    stream.read_exact(&mut read).await?;
+    if read != data {
+        error!("Inconsistent data!");
+    } else {
+        info!("Data matches!");
+    }
+    Ok(())
+}
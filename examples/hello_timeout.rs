@@ -0,0 +1,81 @@
+use async_spdk::{bdev_builtin::*, event::app_stop, *};
+use bdev::*;
+use futures_lite::{future, StreamExt};
+use log::*;
+use std::time::Duration;
+
+fn main() {
+    env_logger::init();
+    event::AppOpts::new()
+        .name("hello_timeout")
+        .config_file(&std::env::args().nth(1).expect("no config_file"))
+        .block_on(async_main())
+        .unwrap();
+}
+
+async fn async_main() -> Result<()> {
+    info!("Start main: hello_timeout");
+
+    // reads on Delay0 take 3 seconds
+    MallocOpts::new("Malloc0", 16384, 512).create().await?;
+    let latencies = DelayLatencies {
+        avg_read: 3_000_000,
+        p99_read: 3_000_000,
+        avg_write: 0,
+        p99_write: 0,
+    };
+    create_delay("Malloc0", "Delay0", latencies).await?;
+
+    let bdev_desc = BdevDesc::create_desc("Delay0")?;
+    let channel = bdev_desc.get_io_channel()?;
+    let mut timeouts = bdev_desc.set_timeout(Duration::from_secs(1))?;
+    let mut read_buf = env::DmaBuf::alloc(512, 0x1000);
+
+    // abort the read once it is reported
+    let (ret, timed_out) = future::zip(
+        bdev_desc.read_blocks(&channel, 0, read_buf.as_mut()),
+        async {
+            let io = timeouts.next().await.expect("no timed out I/O");
+            info!("Timed out: {:?}", io);
+            let aborted = bdev_desc.abort(&channel, &io).await;
+            info!("Abort: {:?}", aborted);
+            io
+        },
+    )
+    .await;
+    info!("Read: {:?}", ret);
+    assert!(matches!(ret, Err(SpdkError::IoFailed(_))));
+
+    // the read has completed, so it can't be aborted again
+    let ret = bdev_desc.abort(&channel, &timed_out).await;
+    info!("Abort of a completed read: {:?}", ret);
+    assert!(
+        matches!(ret, Err(SpdkError::Errno { errno, .. }) if errno == -(spdk_sys::ENOENT as i32))
+    );
+
+    // dropping the future of a read into an owned buffer aborts the read as well
+    let ret = future::or(
+        async {
+            bdev_desc
+                .read_blocks_owned(&channel, 0, env::DmaBuf::alloc(512, 0x1000))
+                .await
+                .map(drop)
+        },
+        async {
+            timeouts.next().await;
+            Err(SpdkError::InvalidIo("timed out".into()))
+        },
+    )
+    .await;
+    info!("Read with the future dropped: {:?}", ret);
+
+    drop(channel);
+    bdev_desc.close();
+
+    delete_delay("Delay0").await?;
+    delete_malloc("Malloc0").await?;
+
+    app_stop();
+
+    Ok(())
+}
@@ -0,0 +1,93 @@
+use async_spdk::{event::app_stop, vbdev::*, *};
+use bdev::*;
+use log::*;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// A vbdev logging every I/O on its way to the base bdev.
+///
+/// It is shared by the channels of all threads, so the I/O are counted atomically.
+struct Tracer {
+    name: String,
+    submitted: AtomicU64,
+}
+
+impl VBdev for Tracer {
+    const NAME: &'static str = "tracer";
+
+    fn submit_request(&self, channel: &PartChannel, io: BdevIo) {
+        let n = self.submitted.fetch_add(1, Ordering::Relaxed);
+        info!(
+            "{} #{}: {:?} blocks {}+{} -> {} block {}",
+            self.name,
+            n,
+            io.io_type(),
+            io.offset_blocks(),
+            io.num_blocks(),
+            channel.base_bdev().name(),
+            io.offset_blocks() + channel.offset_blocks()
+        );
+        let name = self.name.clone();
+        channel.forward_with(io, move |io, result| {
+            info!("{}: {:?} completed: {:?}", name, io.io_type(), result);
+            io.complete(result);
+        });
+    }
+}
+
+fn main() {
+    env_logger::init();
+    event::AppOpts::new()
+        .name("hello_vbdev")
+        .config_file(&std::env::args().nth(1).expect("no config_file"))
+        .block_on(async_main())
+        .unwrap();
+}
+
+async fn async_main() -> Result<()> {
+    info!("Start main: hello_vbdev");
+
+    let base = BDev::get_by_name("Malloc0").expect("no Malloc0");
+    let half = base.num_blocks() / 2;
+    let tracer = |name: &str| Tracer {
+        name: name.into(),
+        submitted: AtomicU64::new(0),
+    };
+    let parts = VBdevOpts::new("Malloc0")
+        .part("Trace0", 0, half, tracer("Trace0"))
+        .part("Trace1", half, half, tracer("Trace1"))
+        .build()?;
+    for part in parts.iter() {
+        info!("Created vbdev: {:?}", part.info());
+    }
+
+    let blk_size = base.get_block_size() as usize;
+    let bdev_desc = BdevDesc::create_desc("Trace1")?;
+    let channel = bdev_desc.get_io_channel()?;
+    let mut write_buf = env::DmaBuf::alloc(blk_size, 0x1000);
+    write_buf.as_mut().fill(0x5a);
+    bdev_desc
+        .write_blocks(&channel, 0, write_buf.as_ref())
+        .await?;
+    info!("Finish writing");
+
+    // the base bdev is claimed by the parts, but can still be read
+    let base_desc = BdevDesc::open("Malloc0", false)?;
+    let base_channel = base_desc.get_io_channel()?;
+    let mut read_buf = env::DmaBuf::alloc(blk_size, 0x1000);
+    base_desc
+        .read_blocks(&base_channel, half, read_buf.as_mut())
+        .await?;
+    if write_buf.as_ref() != read_buf.as_ref() {
+        error!("Inconsistent data!");
+    } else {
+        info!("Data matches at block {} of the base bdev!", half);
+    }
+
+    drop(base_channel);
+    base_desc.close();
+    drop(channel);
+    bdev_desc.close();
+    app_stop();
+
+    Ok(())
+}
@@ -0,0 +1,79 @@
+use async_spdk::{event::app_stop, *};
+use bdev::*;
+use log::*;
+
+fn main() {
+    env_logger::init();
+    event::AppOpts::new()
+        .name("hello_zone")
+        .config_file(&std::env::args().nth(1).expect("no config_file"))
+        .block_on(async_main())
+        .unwrap();
+}
+
+async fn async_main() -> Result<()> {
+    info!("Start main: hello_zone");
+
+    let bdev_desc = BdevDesc::create_desc("Zone0")?;
+    let bdev = bdev_desc.get_bdev()?;
+    assert!(bdev.is_zoned());
+    info!(
+        "Zone size: {}, zones: {}, max open zones: {}",
+        bdev.zone_size(),
+        bdev.num_zones(),
+        bdev.max_open_zones()
+    );
+
+    let blk_size = bdev.get_block_size();
+    let channel = bdev_desc.get_io_channel()?;
+
+    let zones = bdev_desc.report_zones(&channel).await?;
+    info!("Zones: {:?}", zones);
+
+    let zone_id = zones[0].zone_id;
+    bdev_desc
+        .zone_management(&channel, zone_id, ZoneAction::Open)
+        .await?;
+
+    let mut write_buf = env::DmaBuf::alloc(blk_size as usize, 0x1000);
+    write_buf.as_mut().fill(0x5a);
+
+    for _ in 0..2 {
+        let lba = bdev_desc
+            .zone_append(&channel, zone_id, 1, write_buf.as_ref())
+            .await?;
+        info!("Appended at block {}", lba);
+    }
+
+    let info = bdev_desc.get_zone_info(&channel, zone_id, 1).await?;
+    info!("Zone after append: {:?}", info[0]);
+    assert_eq!(info[0].write_pointer, zone_id + 2);
+
+    let mut read_buf = env::DmaBuf::alloc(blk_size as usize, 0x1000);
+    bdev_desc
+        .read(
+            &channel,
+            (zone_id + 1) * blk_size as u64,
+            blk_size as u64,
+            read_buf.as_mut(),
+        )
+        .await?;
+    if write_buf.as_ref() != read_buf.as_ref() {
+        error!("Inconsistent data!");
+    } else {
+        info!("Data matches!");
+    }
+
+    bdev_desc
+        .zone_management(&channel, zone_id, ZoneAction::Reset)
+        .await?;
+    let info = bdev_desc.get_zone_info(&channel, zone_id, 1).await?;
+    assert_eq!(info[0].state, ZoneState::Empty);
+    info!("Zone reset");
+
+    drop(channel);
+    bdev_desc.close();
+    app_stop();
+
+    Ok(())
+}
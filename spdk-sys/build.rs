@@ -16,6 +16,9 @@ fn main() {
     println!("cargo:rustc-link-lib=crypto");
     println!("cargo:rustc-link-lib=stdc++");
     println!("cargo:rustc-link-lib=ssl");
+    if cfg!(feature = "uring") {
+        println!("cargo:rustc-link-lib=uring");
+    }
     println!(
         "cargo:rustc-link-search=native={}",
         env::var("OUT_DIR").unwrap()
@@ -40,8 +43,14 @@ fn main() {
     // The bindgen::Builder is the main entry point
     // to bindgen, and lets you build up options for
     // the resulting bindings.
-    let bindings = bindgen::Builder::default()
+    let mut builder = bindgen::Builder::default();
+    if cfg!(feature = "uring") {
+        builder = builder.clang_arg("-DASYNC_SPDK_URING");
+    }
+    let bindings = builder
         .clang_arg(format!("-I{}", src.join("build/include").display()))
+        // for the headers of the bdev modules
+        .clang_arg(format!("-I{}", src.display()))
         // The input header we would like to generate bindings for.
         .header("wrapper.h")
         .parse_callbacks(Box::new(ignored_macros))
@@ -103,12 +112,15 @@ fn build_from_source() {
     }
 
     // configure
-    let status = Command::new("bash")
+    let mut configure = Command::new("bash");
+    configure
         .current_dir(&src)
         .arg("./configure")
-        .arg("--without-isal")
-        .status()
-        .expect("failed to configure");
+        .arg("--without-isal");
+    if cfg!(feature = "uring") {
+        configure.arg("--with-uring");
+    }
+    let status = configure.status().expect("failed to configure");
     assert!(status.success(), "failed to configure: {}", status);
 
     // make
@@ -127,8 +139,11 @@ fn build_from_source() {
         .arg("-laio")
         .arg("-lnuma")
         .arg("-luuid")
-        .arg("-lcrypto")
-        .arg("-Wl,--whole-archive");
+        .arg("-lcrypto");
+    if cfg!(feature = "uring") {
+        cc.arg("-luring");
+    }
+    cc.arg("-Wl,--whole-archive");
 
     let spdks = std::fs::read_dir(src.join("build/lib")).unwrap();
     let dpdks = std::fs::read_dir(src.join("dpdk/build/lib")).unwrap();
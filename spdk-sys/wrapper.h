@@ -1,4 +1,6 @@
 #include "spdk/bdev.h"
+#include "spdk/bdev_module.h"
+#include "spdk/bdev_zone.h"
 #include "spdk/blob.h"
 #include "spdk/blob_bdev.h"
 #include "spdk/env.h"
@@ -8,3 +10,15 @@
 #include "spdk/vmd.h"
 #include "spdk/log.h"
 #include "spdk/blobfs.h"
+#include "spdk/dif.h"
+#include "module/bdev/malloc/bdev_malloc.h"
+#include "module/bdev/null/bdev_null.h"
+#include "module/bdev/aio/bdev_aio.h"
+#include "module/bdev/split/vbdev_split.h"
+#include "module/bdev/delay/vbdev_delay.h"
+#include "module/bdev/passthru/vbdev_passthru.h"
+#include "module/bdev/error/vbdev_error.h"
+#include "module/bdev/raid/bdev_raid.h"
+#ifdef ASYNC_SPDK_URING
+#include "module/bdev/uring/bdev_uring.h"
+#endif
@@ -1,16 +1,31 @@
 //! BDev wrapper
 
+use crate::bdev_module::module_by_name;
 use crate::complete::LocalComplete;
-use crate::{blob::IoChannel, Result, SpdkError};
+use crate::env;
+use crate::{Result, SpdkError};
 use log::*;
+use serde::{Deserialize, Serialize};
 use spdk_sys::*;
 
+use futures_lite::Stream;
 use std::{
-    ffi::{c_void, CString},
+    any::Any,
+    cell::{Cell, RefCell},
+    collections::{HashMap, VecDeque},
+    ffi::{c_void, CStr, CString},
+    fmt,
+    future::Future,
+    marker::PhantomData,
     mem::MaybeUninit,
+    os::raw::{c_char, c_int},
+    pin::Pin,
+    rc::Rc,
+    task::{Context, Poll, Waker},
+    time::Duration,
 };
 use std::{
-    ops::{Deref, DerefMut},
+    ops::{Deref, DerefMut, Range},
     slice::{from_raw_parts, from_raw_parts_mut},
 };
 
@@ -18,7 +33,7 @@ use std::{
 /// TODO: Implement Drop
 #[derive(Debug)]
 pub struct BDev {
-    ptr: *mut spdk_bdev,
+    pub(crate) ptr: *mut spdk_bdev,
 }
 
 impl BDev {
@@ -32,147 +47,2444 @@ impl BDev {
         Some(BDev { ptr })
     }
 
+    /// Iterate over all registered block devices.
+    pub fn iter() -> BDevIter {
+        BDevIter {
+            next: unsafe { spdk_bdev_first() },
+            leaf: false,
+        }
+    }
+
+    /// Iterate over all registered block devices that are not claimed by other bdevs.
+    pub fn iter_leaves() -> BDevIter {
+        BDevIter {
+            next: unsafe { spdk_bdev_first_leaf() },
+            leaf: true,
+        }
+    }
+
+    /// Get the block device name.
+    pub fn name(&self) -> String {
+        unsafe { to_string(spdk_bdev_get_name(self.ptr)) }
+    }
+
+    /// Get the block device product name.
+    pub fn product_name(&self) -> String {
+        unsafe { to_string(spdk_bdev_get_product_name(self.ptr)) }
+    }
+
+    /// Get the aliases of the block device.
+    pub fn aliases(&self) -> Vec<String> {
+        let mut aliases = vec![];
+        unsafe {
+            let list = spdk_bdev_get_aliases(self.ptr);
+            let mut alias = (*list).tqh_first;
+            while !alias.is_null() {
+                aliases.push(to_string((*alias).alias.name));
+                alias = (*alias).tailq.tqe_next;
+            }
+        }
+        aliases
+    }
+
+    /// Get the UUID of the block device in lower case.
+    pub fn uuid(&self) -> String {
+        let mut buf = [0 as c_char; SPDK_UUID_STRING_LEN as usize];
+        unsafe {
+            spdk_uuid_fmt_lower(buf.as_mut_ptr(), buf.len(), spdk_bdev_get_uuid(self.ptr));
+            to_string(buf.as_ptr())
+        }
+    }
+
+    /// Get the size of the block device in blocks.
+    pub fn num_blocks(&self) -> u64 {
+        unsafe { spdk_bdev_get_num_blocks(self.ptr) }
+    }
+
+    /// Get the size of the metadata in bytes per block.
+    pub fn md_size(&self) -> u32 {
+        unsafe { spdk_bdev_get_md_size(self.ptr) }
+    }
+
+    /// Get the DIF type of the block device.
+    pub fn dif_type(&self) -> DifType {
+        unsafe { spdk_bdev_get_dif_type(self.ptr) }.into()
+    }
+
+    /// Get the size of the data in bytes per block, excluding interleaved metadata.
+    pub fn data_block_size(&self) -> u32 {
+        unsafe { spdk_bdev_get_data_block_size(self.ptr) }
+    }
+
+    /// Whether the metadata is interleaved with the data in each block.
+    pub fn is_md_interleaved(&self) -> bool {
+        unsafe { spdk_bdev_is_md_interleaved(self.ptr) }
+    }
+
+    /// Whether the metadata is stored separately from the data.
+    pub fn is_md_separate(&self) -> bool {
+        unsafe { spdk_bdev_is_md_separate(self.ptr) }
+    }
+
+    /// Whether the DIF is stored in the first 8 bytes of the metadata, otherwise the last 8 bytes.
+    pub fn is_dif_head_of_md(&self) -> bool {
+        unsafe { spdk_bdev_is_dif_head_of_md(self.ptr) }
+    }
+
+    /// Whether the DIF check is enabled on the block device.
+    pub fn is_dif_check_enabled(&self, check: DifCheck) -> bool {
+        unsafe { spdk_bdev_is_dif_check_enabled(self.ptr, check.into()) }
+    }
+
+    /// Whether the block device has a volatile write cache.
+    pub fn has_write_cache(&self) -> bool {
+        unsafe { spdk_bdev_has_write_cache(self.ptr) }
+    }
+
+    /// Get the optimal I/O boundary in blocks, or 0 if there is no boundary.
+    pub fn optimal_io_boundary(&self) -> u32 {
+        unsafe { spdk_bdev_get_optimal_io_boundary(self.ptr) }
+    }
+
+    /// Get the atomic compare and write unit in blocks.
+    pub fn atomic_write_unit(&self) -> u16 {
+        unsafe { spdk_bdev_get_acwu(self.ptr) }
+    }
+
+    /// Get the I/O types supported by the block device.
+    pub fn supported_io_types(&self) -> Vec<IoType> {
+        IoType::ALL
+            .iter()
+            .copied()
+            .filter(|&ty| self.io_type_supported(ty))
+            .collect()
+    }
+
+    /// Collect all properties of the block device.
+    pub fn info(&self) -> BdevInfo {
+        BdevInfo {
+            name: self.name(),
+            product_name: self.product_name(),
+            aliases: self.aliases(),
+            uuid: self.uuid(),
+            block_size: self.get_block_size(),
+            num_blocks: self.num_blocks(),
+            md_size: self.md_size(),
+            dif_type: self.dif_type(),
+            write_cache: self.has_write_cache(),
+            optimal_io_boundary: self.optimal_io_boundary(),
+            atomic_write_unit: self.atomic_write_unit(),
+            supported_io_types: self.supported_io_types(),
+        }
+    }
+
+    /// Get the I/O statistics of the block device, aggregated over all channels.
+    pub async fn device_stat(&self) -> Result<BdevStats> {
+        let mut errors = IoErrorStat::default();
+        let mut stat: spdk_bdev_io_stat = unsafe { std::mem::zeroed() };
+        stat.io_error = &mut errors as *mut IoErrorStat as *mut spdk_bdev_io_error_stat;
+        do_async(|arg| unsafe {
+            spdk_bdev_get_device_stat(
+                self.ptr,
+                &mut stat,
+                spdk_bdev_reset_stat_mode_SPDK_BDEV_RESET_STAT_NONE,
+                Some(stat_callback),
+                arg,
+            );
+        })
+        .await?;
+        Ok(BdevStats::new(&stat, &errors))
+    }
+
+    /// Set the QoS rate limits of the block device.
+    pub async fn set_qos_limits(&self, limits: QosLimits) -> Result<()> {
+        let mut raw = limits.to_raw();
+        do_async(|arg| unsafe {
+            spdk_bdev_set_qos_rate_limits(self.ptr, raw.as_mut_ptr(), Some(qos_callback), arg);
+        })
+        .await
+    }
+
+    /// Get the current QoS rate limits of the block device.
+    pub fn qos_limits(&self) -> QosLimits {
+        let mut raw = [0; NUM_QOS_LIMIT_TYPES];
+        unsafe { spdk_bdev_get_qos_rate_limits(self.ptr, raw.as_mut_ptr()) };
+        QosLimits::from_raw(&raw)
+    }
+
+    /// Whether the block device is zoned.
+    pub fn is_zoned(&self) -> bool {
+        unsafe { spdk_bdev_is_zoned(self.ptr) }
+    }
+
+    /// Get the size of a zone in blocks.
+    pub fn zone_size(&self) -> u64 {
+        unsafe { spdk_bdev_get_zone_size(self.ptr) }
+    }
+
+    /// Get the number of zones.
+    pub fn num_zones(&self) -> u64 {
+        unsafe { spdk_bdev_get_num_zones(self.ptr) }
+    }
+
+    /// Get the maximum number of zones that can be open at the same time, or 0 if unlimited.
+    pub fn max_open_zones(&self) -> u32 {
+        unsafe { spdk_bdev_get_max_open_zones(self.ptr) }
+    }
+
+    /// Get the maximum number of zones that can be active at the same time, or 0 if unlimited.
+    pub fn max_active_zones(&self) -> u32 {
+        unsafe { spdk_bdev_get_max_active_zones(self.ptr) }
+    }
+
+    /// Get the number of zones that should be kept open for the best performance.
+    pub fn optimal_open_zones(&self) -> u32 {
+        unsafe { spdk_bdev_get_optimal_open_zones(self.ptr) }
+    }
+
     pub fn get_block_size(&self) -> u32 {
         let ret = unsafe { spdk_bdev_get_block_size(self.ptr) };
         ret
     }
 
-    pub fn get_buf_align(&self) -> usize {
-        let ret = unsafe { spdk_bdev_get_buf_align(self.ptr) as usize };
-        ret
+    pub fn get_buf_align(&self) -> usize {
+        let ret = unsafe { spdk_bdev_get_buf_align(self.ptr) as usize };
+        ret
+    }
+
+    /// Check whether the block device supports the I/O type.
+    pub fn io_type_supported(&self, io_type: IoType) -> bool {
+        unsafe { spdk_bdev_io_type_supported(self.ptr, io_type.into()) }
+    }
+
+    /// Release the I/O channel, the same as dropping it.
+    pub fn release_io_channel(&self, ioc: BdevIoChannel<'_>) {
+        drop(ioc);
+    }
+}
+
+/// Iterator over registered block devices.
+#[derive(Debug)]
+pub struct BDevIter {
+    next: *mut spdk_bdev,
+    leaf: bool,
+}
+
+impl Iterator for BDevIter {
+    type Item = BDev;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.next.is_null() {
+            return None;
+        }
+        let ptr = self.next;
+        self.next = unsafe {
+            if self.leaf {
+                spdk_bdev_next_leaf(ptr)
+            } else {
+                spdk_bdev_next(ptr)
+            }
+        };
+        Some(BDev { ptr })
+    }
+}
+
+/// Properties of a block device.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BdevInfo {
+    pub name: String,
+    pub product_name: String,
+    pub aliases: Vec<String>,
+    pub uuid: String,
+    pub block_size: u32,
+    pub num_blocks: u64,
+    pub md_size: u32,
+    pub dif_type: DifType,
+    pub write_cache: bool,
+    pub optimal_io_boundary: u32,
+    pub atomic_write_unit: u16,
+    pub supported_io_types: Vec<IoType>,
+}
+
+/// I/O statistics of a block device.
+///
+/// Latencies are measured in ticks, see `ticks_rate` for the number of ticks per second.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub struct BdevStats {
+    pub bytes_read: u64,
+    pub num_read_ops: u64,
+    pub bytes_written: u64,
+    pub num_write_ops: u64,
+    pub bytes_unmapped: u64,
+    pub num_unmap_ops: u64,
+    pub read_latency_ticks: u64,
+    pub max_read_latency_ticks: u64,
+    pub min_read_latency_ticks: u64,
+    pub write_latency_ticks: u64,
+    pub max_write_latency_ticks: u64,
+    pub min_write_latency_ticks: u64,
+    pub unmap_latency_ticks: u64,
+    pub max_unmap_latency_ticks: u64,
+    pub min_unmap_latency_ticks: u64,
+    pub ticks_rate: u64,
+    pub errors: BdevErrorStats,
+}
+
+/// Number of failed I/O of a block device, by completion status.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub struct BdevErrorStats {
+    pub failed: u32,
+    pub nvme_error: u32,
+    pub scsi_error: u32,
+    pub nomem: u32,
+    pub miscompare: u32,
+    pub first_fused_failed: u32,
+    pub aborted: u32,
+    pub aio_error: u32,
+}
+
+/// Number of I/O status codes that indicate an error.
+const NUM_ERROR_STATUS: usize = (-spdk_bdev_io_status_SPDK_BDEV_IO_STATUS_AIO_ERROR) as usize;
+
+/// Mirror of the `spdk_bdev_io_error_stat` private to the bdev library,
+/// which counts errors at index `-status - 1`.
+#[repr(C)]
+#[derive(Debug, Default)]
+struct IoErrorStat {
+    error_status: [u32; NUM_ERROR_STATUS],
+}
+
+impl IoErrorStat {
+    fn get(&self, status: spdk_bdev_io_status) -> u32 {
+        self.error_status[(-status - 1) as usize]
+    }
+}
+
+impl BdevStats {
+    fn new(stat: &spdk_bdev_io_stat, errors: &IoErrorStat) -> Self {
+        BdevStats {
+            bytes_read: stat.bytes_read,
+            num_read_ops: stat.num_read_ops,
+            bytes_written: stat.bytes_written,
+            num_write_ops: stat.num_write_ops,
+            bytes_unmapped: stat.bytes_unmapped,
+            num_unmap_ops: stat.num_unmap_ops,
+            read_latency_ticks: stat.read_latency_ticks,
+            max_read_latency_ticks: stat.max_read_latency_ticks,
+            min_read_latency_ticks: stat.min_read_latency_ticks,
+            write_latency_ticks: stat.write_latency_ticks,
+            max_write_latency_ticks: stat.max_write_latency_ticks,
+            min_write_latency_ticks: stat.min_write_latency_ticks,
+            unmap_latency_ticks: stat.unmap_latency_ticks,
+            max_unmap_latency_ticks: stat.max_unmap_latency_ticks,
+            min_unmap_latency_ticks: stat.min_unmap_latency_ticks,
+            ticks_rate: stat.ticks_rate,
+            errors: BdevErrorStats {
+                failed: errors.get(spdk_bdev_io_status_SPDK_BDEV_IO_STATUS_FAILED),
+                nvme_error: errors.get(spdk_bdev_io_status_SPDK_BDEV_IO_STATUS_NVME_ERROR),
+                scsi_error: errors.get(spdk_bdev_io_status_SPDK_BDEV_IO_STATUS_SCSI_ERROR),
+                nomem: errors.get(spdk_bdev_io_status_SPDK_BDEV_IO_STATUS_NOMEM),
+                miscompare: errors.get(spdk_bdev_io_status_SPDK_BDEV_IO_STATUS_MISCOMPARE),
+                first_fused_failed: errors
+                    .get(spdk_bdev_io_status_SPDK_BDEV_IO_STATUS_FIRST_FUSED_FAILED),
+                aborted: errors.get(spdk_bdev_io_status_SPDK_BDEV_IO_STATUS_ABORTED),
+                aio_error: errors.get(spdk_bdev_io_status_SPDK_BDEV_IO_STATUS_AIO_ERROR),
+            },
+        }
+    }
+
+    /// Compute the statistics of the I/O completed since an earlier sample.
+    ///
+    /// Min and max latencies can not be subtracted, they are taken from `self`.
+    pub fn delta(&self, earlier: &BdevStats) -> BdevStats {
+        BdevStats {
+            bytes_read: self.bytes_read.wrapping_sub(earlier.bytes_read),
+            num_read_ops: self.num_read_ops.wrapping_sub(earlier.num_read_ops),
+            bytes_written: self.bytes_written.wrapping_sub(earlier.bytes_written),
+            num_write_ops: self.num_write_ops.wrapping_sub(earlier.num_write_ops),
+            bytes_unmapped: self.bytes_unmapped.wrapping_sub(earlier.bytes_unmapped),
+            num_unmap_ops: self.num_unmap_ops.wrapping_sub(earlier.num_unmap_ops),
+            read_latency_ticks: self
+                .read_latency_ticks
+                .wrapping_sub(earlier.read_latency_ticks),
+            write_latency_ticks: self
+                .write_latency_ticks
+                .wrapping_sub(earlier.write_latency_ticks),
+            unmap_latency_ticks: self
+                .unmap_latency_ticks
+                .wrapping_sub(earlier.unmap_latency_ticks),
+            errors: BdevErrorStats {
+                failed: self.errors.failed.wrapping_sub(earlier.errors.failed),
+                nvme_error: self
+                    .errors
+                    .nvme_error
+                    .wrapping_sub(earlier.errors.nvme_error),
+                scsi_error: self
+                    .errors
+                    .scsi_error
+                    .wrapping_sub(earlier.errors.scsi_error),
+                nomem: self.errors.nomem.wrapping_sub(earlier.errors.nomem),
+                miscompare: self
+                    .errors
+                    .miscompare
+                    .wrapping_sub(earlier.errors.miscompare),
+                first_fused_failed: self
+                    .errors
+                    .first_fused_failed
+                    .wrapping_sub(earlier.errors.first_fused_failed),
+                aborted: self.errors.aborted.wrapping_sub(earlier.errors.aborted),
+                aio_error: self.errors.aio_error.wrapping_sub(earlier.errors.aio_error),
+            },
+            ..*self
+        }
+    }
+}
+
+/// Number of QoS rate limit types.
+const NUM_QOS_LIMIT_TYPES: usize =
+    spdk_bdev_qos_rate_limit_type_SPDK_BDEV_QOS_NUM_RATE_LIMIT_TYPES as usize;
+
+/// QoS rate limits of a block device, 0 means unlimited.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub struct QosLimits {
+    /// Read and write I/O per second
+    pub rw_ios_per_sec: u64,
+    /// Read and write bandwidth in MB/s
+    pub rw_mbytes_per_sec: u64,
+    /// Read bandwidth in MB/s
+    pub r_mbytes_per_sec: u64,
+    /// Write bandwidth in MB/s
+    pub w_mbytes_per_sec: u64,
+}
+
+impl QosLimits {
+    fn to_raw(self) -> [u64; NUM_QOS_LIMIT_TYPES] {
+        let mut raw = [0; NUM_QOS_LIMIT_TYPES];
+        raw[spdk_bdev_qos_rate_limit_type_SPDK_BDEV_QOS_RW_IOPS_RATE_LIMIT as usize] =
+            self.rw_ios_per_sec;
+        raw[spdk_bdev_qos_rate_limit_type_SPDK_BDEV_QOS_RW_BPS_RATE_LIMIT as usize] =
+            self.rw_mbytes_per_sec;
+        raw[spdk_bdev_qos_rate_limit_type_SPDK_BDEV_QOS_R_BPS_RATE_LIMIT as usize] =
+            self.r_mbytes_per_sec;
+        raw[spdk_bdev_qos_rate_limit_type_SPDK_BDEV_QOS_W_BPS_RATE_LIMIT as usize] =
+            self.w_mbytes_per_sec;
+        raw
+    }
+
+    fn from_raw(raw: &[u64; NUM_QOS_LIMIT_TYPES]) -> Self {
+        QosLimits {
+            rw_ios_per_sec: raw
+                [spdk_bdev_qos_rate_limit_type_SPDK_BDEV_QOS_RW_IOPS_RATE_LIMIT as usize],
+            rw_mbytes_per_sec: raw
+                [spdk_bdev_qos_rate_limit_type_SPDK_BDEV_QOS_RW_BPS_RATE_LIMIT as usize],
+            r_mbytes_per_sec: raw
+                [spdk_bdev_qos_rate_limit_type_SPDK_BDEV_QOS_R_BPS_RATE_LIMIT as usize],
+            w_mbytes_per_sec: raw
+                [spdk_bdev_qos_rate_limit_type_SPDK_BDEV_QOS_W_BPS_RATE_LIMIT as usize],
+        }
+    }
+}
+
+/// State of a zone.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum ZoneState {
+    Empty,
+    ImplicitOpen,
+    ExplicitOpen,
+    Closed,
+    Full,
+    ReadOnly,
+    Offline,
+}
+
+impl From<spdk_bdev_zone_state> for ZoneState {
+    #[allow(non_upper_case_globals)]
+    fn from(state: spdk_bdev_zone_state) -> Self {
+        match state {
+            spdk_bdev_zone_state_SPDK_BDEV_ZONE_STATE_EMPTY => ZoneState::Empty,
+            spdk_bdev_zone_state_SPDK_BDEV_ZONE_STATE_IMP_OPEN => ZoneState::ImplicitOpen,
+            spdk_bdev_zone_state_SPDK_BDEV_ZONE_STATE_EXP_OPEN => ZoneState::ExplicitOpen,
+            spdk_bdev_zone_state_SPDK_BDEV_ZONE_STATE_CLOSED => ZoneState::Closed,
+            spdk_bdev_zone_state_SPDK_BDEV_ZONE_STATE_FULL => ZoneState::Full,
+            spdk_bdev_zone_state_SPDK_BDEV_ZONE_STATE_READ_ONLY => ZoneState::ReadOnly,
+            _ => ZoneState::Offline,
+        }
+    }
+}
+
+/// Information of a zone.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ZoneInfo {
+    /// First logical block of the zone
+    pub zone_id: u64,
+    /// Next logical block to be written in the zone
+    pub write_pointer: u64,
+    /// Number of writable blocks in the zone
+    pub capacity: u64,
+    pub state: ZoneState,
+}
+
+impl From<&spdk_bdev_zone_info> for ZoneInfo {
+    fn from(info: &spdk_bdev_zone_info) -> Self {
+        ZoneInfo {
+            zone_id: info.zone_id,
+            write_pointer: info.write_pointer,
+            capacity: info.capacity,
+            state: info.state.into(),
+        }
+    }
+}
+
+/// Action on a zone.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ZoneAction {
+    Open,
+    Close,
+    Finish,
+    Reset,
+    Offline,
+}
+
+impl From<ZoneAction> for spdk_bdev_zone_action {
+    fn from(action: ZoneAction) -> Self {
+        match action {
+            ZoneAction::Open => spdk_bdev_zone_action_SPDK_BDEV_ZONE_OPEN,
+            ZoneAction::Close => spdk_bdev_zone_action_SPDK_BDEV_ZONE_CLOSE,
+            ZoneAction::Finish => spdk_bdev_zone_action_SPDK_BDEV_ZONE_FINISH,
+            ZoneAction::Reset => spdk_bdev_zone_action_SPDK_BDEV_ZONE_RESET,
+            ZoneAction::Offline => spdk_bdev_zone_action_SPDK_BDEV_ZONE_OFFLINE,
+        }
+    }
+}
+
+/// Data integrity field type.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum DifType {
+    Disable,
+    Type1,
+    Type2,
+    Type3,
+}
+
+impl From<spdk_dif_type> for DifType {
+    #[allow(non_upper_case_globals)]
+    fn from(ty: spdk_dif_type) -> Self {
+        match ty {
+            spdk_dif_type_SPDK_DIF_TYPE1 => DifType::Type1,
+            spdk_dif_type_SPDK_DIF_TYPE2 => DifType::Type2,
+            spdk_dif_type_SPDK_DIF_TYPE3 => DifType::Type3,
+            _ => DifType::Disable,
+        }
+    }
+}
+
+impl From<DifType> for spdk_dif_type {
+    fn from(ty: DifType) -> Self {
+        match ty {
+            DifType::Disable => spdk_dif_type_SPDK_DIF_DISABLE,
+            DifType::Type1 => spdk_dif_type_SPDK_DIF_TYPE1,
+            DifType::Type2 => spdk_dif_type_SPDK_DIF_TYPE2,
+            DifType::Type3 => spdk_dif_type_SPDK_DIF_TYPE3,
+        }
+    }
+}
+
+/// DIF check performed on a block device.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum DifCheck {
+    RefTag,
+    AppTag,
+    Guard,
+}
+
+impl From<DifCheck> for spdk_dif_check_type {
+    fn from(check: DifCheck) -> Self {
+        match check {
+            DifCheck::RefTag => spdk_dif_check_type_SPDK_DIF_CHECK_TYPE_REFTAG,
+            DifCheck::AppTag => spdk_dif_check_type_SPDK_DIF_CHECK_TYPE_APPTAG,
+            DifCheck::Guard => spdk_dif_check_type_SPDK_DIF_CHECK_TYPE_GUARD,
+        }
+    }
+}
+
+/// Bdev I/O type.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum IoType {
+    Read,
+    Write,
+    Unmap,
+    Flush,
+    Reset,
+    NvmeAdmin,
+    NvmeIo,
+    NvmeIoMd,
+    WriteZeroes,
+    Zcopy,
+    GetZoneInfo,
+    ZoneManagement,
+    ZoneAppend,
+    Compare,
+    CompareAndWrite,
+    Abort,
+    SeekHole,
+    SeekData,
+    Copy,
+}
+
+impl IoType {
+    /// All I/O types.
+    pub const ALL: [IoType; 19] = [
+        IoType::Read,
+        IoType::Write,
+        IoType::Unmap,
+        IoType::Flush,
+        IoType::Reset,
+        IoType::NvmeAdmin,
+        IoType::NvmeIo,
+        IoType::NvmeIoMd,
+        IoType::WriteZeroes,
+        IoType::Zcopy,
+        IoType::GetZoneInfo,
+        IoType::ZoneManagement,
+        IoType::ZoneAppend,
+        IoType::Compare,
+        IoType::CompareAndWrite,
+        IoType::Abort,
+        IoType::SeekHole,
+        IoType::SeekData,
+        IoType::Copy,
+    ];
+
+    pub(crate) fn from_raw(io_type: spdk_bdev_io_type) -> Option<Self> {
+        IoType::ALL
+            .iter()
+            .copied()
+            .find(|&ty| spdk_bdev_io_type::from(ty) == io_type)
+    }
+}
+
+impl From<IoType> for spdk_bdev_io_type {
+    fn from(io_type: IoType) -> Self {
+        match io_type {
+            IoType::Read => spdk_bdev_io_type_SPDK_BDEV_IO_TYPE_READ,
+            IoType::Write => spdk_bdev_io_type_SPDK_BDEV_IO_TYPE_WRITE,
+            IoType::Unmap => spdk_bdev_io_type_SPDK_BDEV_IO_TYPE_UNMAP,
+            IoType::Flush => spdk_bdev_io_type_SPDK_BDEV_IO_TYPE_FLUSH,
+            IoType::Reset => spdk_bdev_io_type_SPDK_BDEV_IO_TYPE_RESET,
+            IoType::NvmeAdmin => spdk_bdev_io_type_SPDK_BDEV_IO_TYPE_NVME_ADMIN,
+            IoType::NvmeIo => spdk_bdev_io_type_SPDK_BDEV_IO_TYPE_NVME_IO,
+            IoType::NvmeIoMd => spdk_bdev_io_type_SPDK_BDEV_IO_TYPE_NVME_IO_MD,
+            IoType::WriteZeroes => spdk_bdev_io_type_SPDK_BDEV_IO_TYPE_WRITE_ZEROES,
+            IoType::Zcopy => spdk_bdev_io_type_SPDK_BDEV_IO_TYPE_ZCOPY,
+            IoType::GetZoneInfo => spdk_bdev_io_type_SPDK_BDEV_IO_TYPE_GET_ZONE_INFO,
+            IoType::ZoneManagement => spdk_bdev_io_type_SPDK_BDEV_IO_TYPE_ZONE_MANAGEMENT,
+            IoType::ZoneAppend => spdk_bdev_io_type_SPDK_BDEV_IO_TYPE_ZONE_APPEND,
+            IoType::Compare => spdk_bdev_io_type_SPDK_BDEV_IO_TYPE_COMPARE,
+            IoType::CompareAndWrite => spdk_bdev_io_type_SPDK_BDEV_IO_TYPE_COMPARE_AND_WRITE,
+            IoType::Abort => spdk_bdev_io_type_SPDK_BDEV_IO_TYPE_ABORT,
+            IoType::SeekHole => spdk_bdev_io_type_SPDK_BDEV_IO_TYPE_SEEK_HOLE,
+            IoType::SeekData => spdk_bdev_io_type_SPDK_BDEV_IO_TYPE_SEEK_DATA,
+            IoType::Copy => spdk_bdev_io_type_SPDK_BDEV_IO_TYPE_COPY,
+        }
+    }
+}
+
+/// Largest amount of data moved by one read and write when a bdev can't copy by itself.
+pub const COPY_CHUNK_SIZE: u64 = 1 << 20;
+
+/// Descriptor of an opened bdev, closed on drop.
+///
+/// The futures of I/O on borrowed buffers must be awaited until the I/O completes:
+/// dropping one doesn't stop the I/O, so the bdev may still access the buffer.
+/// Futures of I/O that own their buffers, like `read_blocks_owned`, or that use no
+/// buffer, like `unmap`, abort the I/O when they are dropped.
+#[derive(Debug)]
+pub struct BdevDesc {
+    pub(crate) ptr: *mut spdk_bdev_desc,
+    events: Rc<RefCell<EventQueue>>,
+    timeouts: Rc<RefCell<EventQueue<TimedOutIo>>>,
+}
+
+impl Drop for BdevDesc {
+    fn drop(&mut self) {
+        unsafe {
+            spdk_bdev_close(self.ptr);
+        }
+        self.events.borrow_mut().close();
+        self.timeouts.borrow_mut().close();
+    }
+}
+
+/// I/O channel of a bdev descriptor, released on drop.
+///
+/// A channel must be used and dropped on the thread that got it.
+#[derive(Debug)]
+pub struct BdevIoChannel<'a> {
+    pub(crate) ptr: *mut spdk_io_channel,
+    _desc: PhantomData<&'a BdevDesc>,
+}
+
+impl Drop for BdevIoChannel<'_> {
+    fn drop(&mut self) {
+        unsafe {
+            spdk_put_io_channel(self.ptr);
+        }
+    }
+}
+
+impl BdevDesc {
+    /// Open the bdev for reading and writing.
+    pub fn create_desc(name: &str) -> Result<Self> {
+        Self::open(name, true)
+    }
+
+    /// Open the bdev, read-only if `write` is false.
+    ///
+    /// Opening for write fails if the bdev is claimed by a module that does not share writes,
+    /// e.g. when it is owned by a blobstore.
+    pub fn open(name: &str, write: bool) -> Result<Self> {
+        let cname = CString::new(name).expect("Could not parse to CString");
+        let mut ptr = MaybeUninit::uninit();
+        let events = Rc::new(RefCell::new(EventQueue::default()));
+        let err = unsafe {
+            spdk_bdev_open_ext(
+                cname.as_ptr(),
+                write,
+                Some(event_callback),
+                Rc::as_ptr(&events) as *mut c_void,
+                ptr.as_mut_ptr(),
+            )
+        };
+        SpdkError::from_retval(err)?;
+        Ok(BdevDesc {
+            ptr: unsafe { ptr.assume_init() },
+            events,
+            timeouts: Rc::new(RefCell::new(EventQueue::default())),
+        })
+    }
+
+    /// Get the I/O statistics of the bdev on the I/O channel.
+    pub fn io_stat(&self, io_channel: &BdevIoChannel<'_>) -> Result<BdevStats> {
+        let bdev = self.get_bdev()?;
+        let mut errors = IoErrorStat::default();
+        let mut stat: spdk_bdev_io_stat = unsafe { std::mem::zeroed() };
+        stat.io_error = &mut errors as *mut IoErrorStat as *mut spdk_bdev_io_error_stat;
+        unsafe {
+            spdk_bdev_get_io_stat(
+                bdev.ptr,
+                io_channel.ptr,
+                &mut stat,
+                spdk_bdev_reset_stat_mode_SPDK_BDEV_RESET_STAT_NONE,
+            )
+        };
+        Ok(BdevStats::new(&stat, &errors))
+    }
+
+    /// write data with a separate metadata buffer, addressed in blocks
+    ///
+    /// The bdev must be formatted with separate metadata.
+    pub async fn write_blocks_with_md(
+        &self,
+        io_channel: &BdevIoChannel<'_>,
+        offset_blocks: u64,
+        num_blocks: u64,
+        buf: &[u8],
+        md_buf: &[u8],
+    ) -> Result<()> {
+        self.check_blocks(offset_blocks, num_blocks, buf.len())?;
+        let md_size = self.get_bdev()?.md_size() as u64;
+        check_buf_len(md_buf.len(), num_blocks * md_size)?;
+        self.submit(io_channel, |arg| unsafe {
+            spdk_bdev_write_blocks_with_md(
+                self.ptr,
+                io_channel.ptr,
+                buf.as_ptr() as _,
+                md_buf.as_ptr() as _,
+                offset_blocks,
+                num_blocks,
+                Some(callback),
+                arg,
+            )
+        })
+        .await
+    }
+
+    /// read data with a separate metadata buffer, addressed in blocks
+    ///
+    /// The bdev must be formatted with separate metadata.
+    pub async fn read_blocks_with_md(
+        &self,
+        io_channel: &BdevIoChannel<'_>,
+        offset_blocks: u64,
+        num_blocks: u64,
+        buf: &mut [u8],
+        md_buf: &mut [u8],
+    ) -> Result<()> {
+        self.check_blocks(offset_blocks, num_blocks, buf.len())?;
+        let md_size = self.get_bdev()?.md_size() as u64;
+        check_buf_len(md_buf.len(), num_blocks * md_size)?;
+        self.submit(io_channel, |arg| unsafe {
+            spdk_bdev_read_blocks_with_md(
+                self.ptr,
+                io_channel.ptr,
+                buf.as_mut_ptr() as _,
+                md_buf.as_mut_ptr() as _,
+                offset_blocks,
+                num_blocks,
+                Some(callback),
+                arg,
+            )
+        })
+        .await
+    }
+
+    /// get the information of `num_zones` zones starting from `zone_id`
+    pub async fn get_zone_info(
+        &self,
+        io_channel: &BdevIoChannel<'_>,
+        zone_id: u64,
+        num_zones: usize,
+    ) -> Result<Vec<ZoneInfo>> {
+        self.check_io_type(IoType::GetZoneInfo)?;
+        let infos: Vec<spdk_bdev_zone_info> = Vec::with_capacity(num_zones);
+        // the infos are owned by the I/O, so it can be aborted
+        let (ret, mut infos) = self
+            .submit_keeping(io_channel, true, infos, |infos, arg| unsafe {
+                spdk_bdev_get_zone_info(
+                    self.ptr,
+                    io_channel.ptr,
+                    zone_id,
+                    num_zones,
+                    infos.as_mut_ptr(),
+                    Some(callback),
+                    arg,
+                )
+            })
+            .await;
+        ret?;
+        unsafe { infos.set_len(num_zones) };
+        Ok(infos.iter().map(ZoneInfo::from).collect())
+    }
+
+    /// get the information of all zones on the bdev
+    pub async fn report_zones(&self, io_channel: &BdevIoChannel<'_>) -> Result<Vec<ZoneInfo>> {
+        let num_zones = self.get_bdev()?.num_zones() as usize;
+        self.get_zone_info(io_channel, 0, num_zones).await
+    }
+
+    /// open, close, finish, reset or offline the zone starting at `zone_id`
+    pub async fn zone_management(
+        &self,
+        io_channel: &BdevIoChannel<'_>,
+        zone_id: u64,
+        action: ZoneAction,
+    ) -> Result<()> {
+        self.check_io_type(IoType::ZoneManagement)?;
+        self.submit_abortable(io_channel, |arg| unsafe {
+            spdk_bdev_zone_management(
+                self.ptr,
+                io_channel.ptr,
+                zone_id,
+                action.into(),
+                Some(callback),
+                arg,
+            )
+        })
+        .await
+    }
+
+    /// append data to the zone starting at `zone_id`
+    ///
+    /// Return the first logical block the data is written to, or `InvalidIo` if the
+    /// buffer is shorter than `num_blocks`.
+    pub async fn zone_append(
+        &self,
+        io_channel: &BdevIoChannel<'_>,
+        zone_id: u64,
+        num_blocks: u64,
+        buf: &[u8],
+    ) -> Result<u64> {
+        self.check_io_type(IoType::ZoneAppend)?;
+        self.check_blocks(zone_id, num_blocks, buf.len())?;
+        self.submit(io_channel, |arg| unsafe {
+            spdk_bdev_zone_append(
+                self.ptr,
+                io_channel.ptr,
+                buf.as_ptr() as _,
+                zone_id,
+                num_blocks,
+                Some(append_callback),
+                arg,
+            )
+        })
+        .await
+    }
+
+    /// send an NVMe admin command to the controller of the bdev
+    ///
+    /// Return `Unsupported` if the bdev is not backed by NVMe.
+    pub async fn nvme_admin_passthru(
+        &self,
+        io_channel: &BdevIoChannel<'_>,
+        cmd: &NvmeCmd,
+        buf: &mut DmaBuf,
+    ) -> Result<NvmeCompletion> {
+        self.check_io_type(IoType::NvmeAdmin)?;
+        let cmd = cmd.to_raw();
+        self.submit(io_channel, |arg| unsafe {
+            spdk_bdev_nvme_admin_passthru(
+                self.ptr,
+                io_channel.ptr,
+                &cmd,
+                buf.buf,
+                buf.length,
+                Some(nvme_callback),
+                arg,
+            )
+        })
+        .await
+    }
+
+    /// send an NVMe I/O command to the namespace of the bdev
+    ///
+    /// Return `Unsupported` if the bdev is not backed by NVMe.
+    pub async fn nvme_io_passthru(
+        &self,
+        io_channel: &BdevIoChannel<'_>,
+        cmd: &NvmeCmd,
+        buf: &mut DmaBuf,
+    ) -> Result<NvmeCompletion> {
+        self.check_io_type(IoType::NvmeIo)?;
+        let cmd = cmd.to_raw();
+        self.submit(io_channel, |arg| unsafe {
+            spdk_bdev_nvme_io_passthru(
+                self.ptr,
+                io_channel.ptr,
+                &cmd,
+                buf.buf,
+                buf.length,
+                Some(nvme_callback),
+                arg,
+            )
+        })
+        .await
+    }
+
+    /// send an NVMe I/O command with a separate metadata buffer to the namespace of the bdev
+    ///
+    /// Return `Unsupported` if the bdev is not backed by NVMe.
+    pub async fn nvme_io_passthru_md(
+        &self,
+        io_channel: &BdevIoChannel<'_>,
+        cmd: &NvmeCmd,
+        buf: &mut DmaBuf,
+        md_buf: &mut DmaBuf,
+    ) -> Result<NvmeCompletion> {
+        self.check_io_type(IoType::NvmeIoMd)?;
+        let cmd = cmd.to_raw();
+        self.submit(io_channel, |arg| unsafe {
+            spdk_bdev_nvme_io_passthru_md(
+                self.ptr,
+                io_channel.ptr,
+                &cmd,
+                buf.buf,
+                buf.length,
+                md_buf.buf,
+                md_buf.length,
+                Some(nvme_callback),
+                arg,
+            )
+        })
+        .await
+    }
+
+    /// Claim the bdev through this descriptor.
+    ///
+    /// `name` is shown to other openers that conflict with the claim.
+    /// The claim is released when the descriptor is closed.
+    pub fn claim(&self, claim_type: ClaimType, name: &str) -> Result<()> {
+        let mut opts = MaybeUninit::uninit();
+        let mut opts = unsafe {
+            spdk_bdev_claim_opts_init(
+                opts.as_mut_ptr(),
+                std::mem::size_of::<spdk_bdev_claim_opts>(),
+            );
+            opts.assume_init()
+        };
+        // keep the name null terminated
+        let len = name.len().min(opts.name.len() - 1);
+        for (dst, src) in opts.name.iter_mut().zip(&name.as_bytes()[..len]) {
+            *dst = *src as c_char;
+        }
+        if let ClaimType::ReadManyWriteShared(key) = claim_type {
+            opts.shared_claim_key = key;
+        }
+        let err = unsafe {
+            spdk_bdev_module_claim_bdev_desc(self.ptr, claim_type.into(), &mut opts, claim_module())
+        };
+        SpdkError::from_retval(err)
+    }
+
+    /// Get the stream of events on the opened bdev.
+    ///
+    /// The stream ends after the descriptor is closed.
+    /// Return `None` while another stream of the descriptor is alive.
+    pub fn events(&self) -> Option<BdevEventStream> {
+        BdevEventStream::take(&self.events)
+    }
+
+    /// Report the I/O submitted through this descriptor that have not completed
+    /// within `timeout`, rounded up to whole seconds.
+    ///
+    /// The channels are checked about once per second, and an I/O is reported on
+    /// every check until it completes or is aborted. A zero timeout disables the checks.
+    /// The stream ends after the descriptor is closed.
+    pub fn set_timeout(&self, timeout: Duration) -> Result<TimeoutStream> {
+        let mut secs = timeout.as_secs();
+        if timeout.subsec_nanos() != 0 {
+            secs += 1;
+        }
+        let err = unsafe {
+            spdk_bdev_set_timeout(
+                self.ptr,
+                secs,
+                Some(timeout_callback),
+                Rc::as_ptr(&self.timeouts) as *mut c_void,
+            )
+        };
+        SpdkError::from_retval(err)?;
+        Ok(TimeoutStream {
+            queue: self.timeouts.clone(),
+        })
+    }
+
+    /// Abort an I/O that has timed out.
+    ///
+    /// `io_channel` must be the channel the I/O was submitted on. The aborted I/O
+    /// fails with an aborted status. The abort fails with `-ENOENT` if the I/O has
+    /// already completed or was not submitted by a future of the descriptor, and fails
+    /// if the bdev doesn't support `IoType::Abort`.
+    pub async fn abort(&self, io_channel: &BdevIoChannel<'_>, io: &TimedOutIo) -> Result<()> {
+        if io.channel != io_channel.ptr {
+            return Err(SpdkError::InvalidIo(
+                "the I/O was submitted on another channel".into(),
+            ));
+        }
+        self.submit_abortable(io_channel, |arg| unsafe {
+            // the completion may have been reused by another I/O while waiting to submit
+            if io.generation.is_none() || io.generation != generation_of(io.caller_ctx) {
+                return -(ENOENT as i32);
+            }
+            spdk_bdev_abort(self.ptr, io_channel.ptr, io.caller_ctx, Some(callback), arg)
+        })
+        .await
+    }
+
+    pub fn get_bdev(&self) -> Result<BDev> {
+        let ptr = unsafe { spdk_bdev_desc_get_bdev(self.ptr) };
+        if ptr.is_null() {
+            return Err(SpdkError::from(-1));
+        }
+        Ok(BDev { ptr })
+    }
+
+    /// Get the I/O channel of the bdev on the current thread.
+    ///
+    /// The channel borrows the descriptor, so the descriptor can't be closed before
+    /// all its channels are released.
+    pub fn get_io_channel(&self) -> Result<BdevIoChannel<'_>> {
+        let ptr = unsafe { spdk_bdev_get_io_channel(self.ptr) };
+        if ptr.is_null() {
+            return Err(SpdkError::from(-1));
+        }
+        Ok(BdevIoChannel {
+            ptr,
+            _desc: PhantomData,
+        })
+    }
+
+    /// Close the descriptor, the same as dropping it.
+    pub fn close(self) {
+        drop(self);
+    }
+
+    /// write data at offset
+    ///
+    /// Return `InvalidIo` if `length` exceeds the buffer.
+    ///
+    /// spdk_bdev_write return 0 for success
+    pub async fn write(
+        &self,
+        io_channel: &BdevIoChannel<'_>,
+        offset: u64,
+        length: u64,
+        buf: &[u8],
+    ) -> Result<()> {
+        check_buf_len(buf.len(), length)?;
+        self.submit(io_channel, |arg| unsafe {
+            spdk_bdev_write(
+                self.ptr,
+                io_channel.ptr,
+                buf.as_ptr() as _,
+                offset,
+                length,
+                Some(callback),
+                arg,
+            )
+        })
+        .await
+    }
+
+    /// read data at offset
+    ///
+    /// Return `InvalidIo` if `length` exceeds the buffer.
+    ///
+    /// spdk_bdev_read return 0 for success
+    pub async fn read(
+        &self,
+        io_channel: &BdevIoChannel<'_>,
+        offset: u64,
+        length: u64,
+        buf: &mut [u8],
+    ) -> Result<()> {
+        check_buf_len(buf.len(), length)?;
+        self.submit(io_channel, |arg| unsafe {
+            spdk_bdev_read(
+                self.ptr,
+                io_channel.ptr,
+                buf.as_mut_ptr() as _,
+                offset,
+                length,
+                Some(callback),
+                arg,
+            )
+        })
+        .await
+    }
+
+    /// write data from a list of buffers at offset
+    ///
+    /// Return `InvalidIo` if `length` exceeds the buffers.
+    ///
+    /// spdk_bdev_writev return 0 for success
+    pub async fn writev(
+        &self,
+        io_channel: &BdevIoChannel<'_>,
+        offset: u64,
+        length: u64,
+        bufs: &[DmaBuf],
+    ) -> Result<()> {
+        check_buf_len(bufs.iter().map(|buf| buf.length).sum(), length)?;
+        let iovs = to_iovecs(bufs);
+        self.submit_keeping(io_channel, false, iovs, |iovs, arg| unsafe {
+            spdk_bdev_writev(
+                self.ptr,
+                io_channel.ptr,
+                iovs.as_mut_ptr(),
+                iovs.len() as i32,
+                offset,
+                length,
+                Some(callback),
+                arg,
+            )
+        })
+        .await
+        .0
+    }
+
+    /// read data at offset into a list of buffers
+    ///
+    /// Return `InvalidIo` if `length` exceeds the buffers.
+    ///
+    /// spdk_bdev_readv return 0 for success
+    pub async fn readv(
+        &self,
+        io_channel: &BdevIoChannel<'_>,
+        offset: u64,
+        length: u64,
+        bufs: &mut [DmaBuf],
+    ) -> Result<()> {
+        check_buf_len(bufs.iter().map(|buf| buf.length).sum(), length)?;
+        let iovs = to_iovecs(bufs);
+        self.submit_keeping(io_channel, false, iovs, |iovs, arg| unsafe {
+            spdk_bdev_readv(
+                self.ptr,
+                io_channel.ptr,
+                iovs.as_mut_ptr(),
+                iovs.len() as i32,
+                offset,
+                length,
+                Some(callback),
+                arg,
+            )
+        })
+        .await
+        .0
+    }
+
+    /// write the whole buffer at `offset_blocks`, addressed in blocks
+    ///
+    /// The number of blocks is derived from the buffer, which must be a multiple of
+    /// the block size, aligned to `BDev::get_buf_align` and fit in the bdev.
+    /// Return `InvalidIo` otherwise.
+    pub async fn write_blocks(
+        &self,
+        io_channel: &BdevIoChannel<'_>,
+        offset_blocks: u64,
+        buf: &[u8],
+    ) -> Result<()> {
+        let num_blocks = self.check_buf(offset_blocks, buf.as_ptr(), buf.len())?;
+        self.submit(io_channel, |arg| unsafe {
+            spdk_bdev_write_blocks(
+                self.ptr,
+                io_channel.ptr,
+                buf.as_ptr() as _,
+                offset_blocks,
+                num_blocks,
+                Some(callback),
+                arg,
+            )
+        })
+        .await
+    }
+
+    /// read blocks from `offset_blocks` to fill the whole buffer
+    ///
+    /// The buffer is validated the same way as in `write_blocks`.
+    pub async fn read_blocks(
+        &self,
+        io_channel: &BdevIoChannel<'_>,
+        offset_blocks: u64,
+        buf: &mut [u8],
+    ) -> Result<()> {
+        let num_blocks = self.check_buf(offset_blocks, buf.as_ptr(), buf.len())?;
+        self.submit(io_channel, |arg| unsafe {
+            spdk_bdev_read_blocks(
+                self.ptr,
+                io_channel.ptr,
+                buf.as_mut_ptr() as _,
+                offset_blocks,
+                num_blocks,
+                Some(callback),
+                arg,
+            )
+        })
+        .await
+    }
+
+    /// read `num_blocks` blocks from `offset_blocks` into a buffer supplied by the bdev layer
+    ///
+    /// The data is borrowed from the I/O, which is freed when the guard is dropped,
+    /// so no `DmaBuf` has to be allocated. Drop the guard soon since it holds a
+    /// `spdk_bdev_io` of the channel.
+    pub async fn read_blocks_borrowed<'a>(
+        &self,
+        io_channel: &'a BdevIoChannel<'a>,
+        offset_blocks: u64,
+        num_blocks: u64,
+    ) -> Result<ReadGuard<'a>> {
+        let block_size = self.get_bdev()?.get_block_size() as u64;
+        self.check_blocks(
+            offset_blocks,
+            num_blocks,
+            (num_blocks * block_size) as usize,
+        )?;
+        self.submit_abortable::<ReadGuard<'static>>(io_channel, |arg| unsafe {
+            spdk_bdev_read_blocks(
+                self.ptr,
+                io_channel.ptr,
+                std::ptr::null_mut(),
+                offset_blocks,
+                num_blocks,
+                Some(borrowed_callback),
+                arg,
+            )
+        })
+        .await
+    }
+
+    /// write the whole owned buffer at `offset_blocks`, and give it back
+    ///
+    /// The buffer is validated the same way as in `write_blocks`. If the future is
+    /// dropped before the write completes, the write is aborted and the buffer is freed
+    /// when it completes.
+    pub async fn write_blocks_owned(
+        &self,
+        io_channel: &BdevIoChannel<'_>,
+        offset_blocks: u64,
+        buf: env::DmaBuf,
+    ) -> Result<env::DmaBuf> {
+        let len = buf.as_ref().len();
+        self.submit_owned_blocks(io_channel, true, offset_blocks, buf, 0..len)
+            .await
+    }
+
+    /// read blocks from `offset_blocks` to fill the whole owned buffer, and give it back
+    ///
+    /// The buffer is validated the same way as in `write_blocks`. If the future is
+    /// dropped before the read completes, the read is aborted and the buffer is freed
+    /// when it completes.
+    pub async fn read_blocks_owned(
+        &self,
+        io_channel: &BdevIoChannel<'_>,
+        offset_blocks: u64,
+        buf: env::DmaBuf,
+    ) -> Result<env::DmaBuf> {
+        let len = buf.as_ref().len();
+        self.submit_owned_blocks(io_channel, false, offset_blocks, buf, 0..len)
+            .await
+    }
+
+    /// Write or read the blocks at `offset_blocks` from or into `range` of an owned buffer.
+    ///
+    /// The range is validated like a buffer of `write_blocks`.
+    pub(crate) async fn submit_owned_blocks(
+        &self,
+        io_channel: &BdevIoChannel<'_>,
+        write: bool,
+        offset_blocks: u64,
+        buf: env::DmaBuf,
+        range: Range<usize>,
+    ) -> Result<env::DmaBuf> {
+        let data = &buf.as_ref()[range.clone()];
+        let num_blocks = self.check_buf(offset_blocks, data.as_ptr(), data.len())?;
+        let (ret, buf) = self
+            .submit_keeping(io_channel, true, buf, |buf, arg| unsafe {
+                let ptr = buf.as_mut()[range.clone()].as_mut_ptr() as _;
+                if write {
+                    spdk_bdev_write_blocks(
+                        self.ptr,
+                        io_channel.ptr,
+                        ptr,
+                        offset_blocks,
+                        num_blocks,
+                        Some(callback),
+                        arg,
+                    )
+                } else {
+                    spdk_bdev_read_blocks(
+                        self.ptr,
+                        io_channel.ptr,
+                        ptr,
+                        offset_blocks,
+                        num_blocks,
+                        Some(callback),
+                        arg,
+                    )
+                }
+            })
+            .await;
+        ret.map(|()| buf)
+    }
+
+    /// write data from a list of buffers, addressed in blocks
+    ///
+    /// spdk_bdev_writev_blocks return 0 for success
+    pub async fn writev_blocks(
+        &self,
+        io_channel: &BdevIoChannel<'_>,
+        offset_blocks: u64,
+        num_blocks: u64,
+        bufs: &[DmaBuf],
+    ) -> Result<()> {
+        let len = bufs.iter().map(|buf| buf.length).sum::<usize>();
+        self.check_blocks(offset_blocks, num_blocks, len)?;
+        let iovs = to_iovecs(bufs);
+        self.submit_keeping(io_channel, false, iovs, |iovs, arg| unsafe {
+            spdk_bdev_writev_blocks(
+                self.ptr,
+                io_channel.ptr,
+                iovs.as_mut_ptr(),
+                iovs.len() as i32,
+                offset_blocks,
+                num_blocks,
+                Some(callback),
+                arg,
+            )
+        })
+        .await
+        .0
+    }
+
+    /// read data into a list of buffers, addressed in blocks
+    ///
+    /// spdk_bdev_readv_blocks return 0 for success
+    pub async fn readv_blocks(
+        &self,
+        io_channel: &BdevIoChannel<'_>,
+        offset_blocks: u64,
+        num_blocks: u64,
+        bufs: &mut [DmaBuf],
+    ) -> Result<()> {
+        let len = bufs.iter().map(|buf| buf.length).sum::<usize>();
+        self.check_blocks(offset_blocks, num_blocks, len)?;
+        let iovs = to_iovecs(bufs);
+        self.submit_keeping(io_channel, false, iovs, |iovs, arg| unsafe {
+            spdk_bdev_readv_blocks(
+                self.ptr,
+                io_channel.ptr,
+                iovs.as_mut_ptr(),
+                iovs.len() as i32,
+                offset_blocks,
+                num_blocks,
+                Some(callback),
+                arg,
+            )
+        })
+        .await
+        .0
+    }
+
+    /// Submit an I/O with `f` and wait for its completion.
+    ///
+    /// If the channel has run out of `spdk_bdev_io` (`-ENOMEM`), wait until the bdev
+    /// layer frees some of them and submit again.
+    /// The I/O may borrow buffers, so it is not aborted if the future is dropped.
+    async fn submit<T: Unpin>(
+        &self,
+        io_channel: &BdevIoChannel<'_>,
+        mut f: impl FnMut(*mut c_void) -> i32,
+    ) -> Result<T> {
+        self.submit_keeping(io_channel, false, (), |_, arg| f(arg))
+            .await
+            .0
+    }
+
+    /// Submit an I/O without buffers with `f` like `submit`, aborting it if the future
+    /// is dropped before it completes.
+    async fn submit_abortable<T: Unpin>(
+        &self,
+        io_channel: &BdevIoChannel<'_>,
+        mut f: impl FnMut(*mut c_void) -> i32,
+    ) -> Result<T> {
+        self.submit_keeping(io_channel, true, (), |_, arg| f(arg))
+            .await
+            .0
+    }
+
+    /// Submit an I/O using `keep` with `f` like `submit`, and give `keep` back.
+    ///
+    /// `keep` is freed by the completion callback if the future is dropped, so it stays
+    /// valid for the bdev layer until the I/O completes.
+    async fn submit_keeping<T: Unpin, K: Unpin + 'static>(
+        &self,
+        io_channel: &BdevIoChannel<'_>,
+        abort_on_drop: bool,
+        keep: K,
+        mut f: impl FnMut(&mut K, *mut c_void) -> i32,
+    ) -> (Result<T>, K) {
+        let mut in_flight = InFlight::<T, K>::new(self.ptr, io_channel.ptr, abort_on_drop, keep);
+        loop {
+            let arg = in_flight.as_arg();
+            let rc = f(in_flight.keep.as_mut().unwrap(), arg);
+            if rc == 0 {
+                in_flight.set_submitted();
+                let ret = (&mut in_flight).await;
+                return (ret, in_flight.keep.take().unwrap());
+            }
+            if rc != -(ENOMEM as i32) {
+                return (Err(SpdkError::from(rc)), in_flight.keep.take().unwrap());
+            }
+            match self.get_bdev() {
+                Ok(bdev) => IoWaitEntry::new(bdev.ptr, io_channel.ptr).await,
+                Err(e) => return (Err(e), in_flight.keep.take().unwrap()),
+            }
+        }
+    }
+
+    /// Check that a buffer for a whole-buffer I/O at `offset_blocks` is block sized,
+    /// aligned and in range, and return its length in blocks.
+    pub(crate) fn check_buf(&self, offset_blocks: u64, ptr: *const u8, len: usize) -> Result<u64> {
+        let bdev = self.get_bdev()?;
+        let block_size = bdev.get_block_size() as usize;
+        if len == 0 || !len.is_multiple_of(block_size) {
+            return Err(SpdkError::InvalidIo(format!(
+                "buffer length {} is not a multiple of the block size {}",
+                len, block_size
+            )));
+        }
+        let align = bdev.get_buf_align();
+        if !(ptr as usize).is_multiple_of(align) {
+            return Err(SpdkError::InvalidIo(format!(
+                "buffer {:p} is not aligned to {} bytes",
+                ptr, align
+            )));
+        }
+        let num_blocks = (len / block_size) as u64;
+        self.check_blocks(offset_blocks, num_blocks, len)?;
+        Ok(num_blocks)
+    }
+
+    /// Check that the block range is inside the bdev and the buffers of
+    /// `len` bytes can hold it.
+    fn check_blocks(&self, offset_blocks: u64, num_blocks: u64, len: usize) -> Result<()> {
+        let bdev = self.get_bdev()?;
+        match offset_blocks.checked_add(num_blocks) {
+            Some(end) if end <= bdev.num_blocks() => {}
+            _ => {
+                return Err(SpdkError::InvalidIo(format!(
+                    "blocks {}+{} out of range of {} blocks",
+                    offset_blocks,
+                    num_blocks,
+                    bdev.num_blocks()
+                )))
+            }
+        }
+        check_buf_len(len, num_blocks * bdev.get_block_size() as u64)
+    }
+
+    /// Return `Unsupported` if the underlying bdev can not handle the I/O type.
+    fn check_io_type(&self, io_type: IoType) -> Result<()> {
+        if !self.get_bdev()?.io_type_supported(io_type) {
+            return Err(SpdkError::Unsupported(io_type));
+        }
+        Ok(())
+    }
+
+    /// unmap (trim) a range of data at offset
+    ///
+    /// spdk_bdev_unmap return 0 for success
+    pub async fn unmap(
+        &self,
+        io_channel: &BdevIoChannel<'_>,
+        offset: u64,
+        length: u64,
+    ) -> Result<()> {
+        self.check_io_type(IoType::Unmap)?;
+        self.submit_abortable(io_channel, |arg| unsafe {
+            spdk_bdev_unmap(
+                self.ptr,
+                io_channel.ptr,
+                offset,
+                length,
+                Some(callback),
+                arg,
+            )
+        })
+        .await
+    }
+
+    /// flush a range of data at offset from volatile cache to persistent media
+    ///
+    /// spdk_bdev_flush return 0 for success
+    pub async fn flush(
+        &self,
+        io_channel: &BdevIoChannel<'_>,
+        offset: u64,
+        length: u64,
+    ) -> Result<()> {
+        self.check_io_type(IoType::Flush)?;
+        self.submit_abortable(io_channel, |arg| unsafe {
+            spdk_bdev_flush(
+                self.ptr,
+                io_channel.ptr,
+                offset,
+                length,
+                Some(callback),
+                arg,
+            )
+        })
+        .await
+    }
+
+    /// write zeroes to a range of data at offset
+    ///
+    /// spdk_bdev_write_zeroes return 0 for success
+    pub async fn write_zeroes(
+        &self,
+        io_channel: &BdevIoChannel<'_>,
+        offset: u64,
+        length: u64,
+    ) -> Result<()> {
+        self.check_io_type(IoType::WriteZeroes)?;
+        self.submit_abortable(io_channel, |arg| unsafe {
+            spdk_bdev_write_zeroes(
+                self.ptr,
+                io_channel.ptr,
+                offset,
+                length,
+                Some(callback),
+                arg,
+            )
+        })
+        .await
+    }
+
+    /// copy `num_blocks` blocks from `src_offset_blocks` to `dst_offset_blocks`
+    ///
+    /// The bdev moves the data itself if it supports `IoType::Copy`, otherwise the data
+    /// is read into a DMA buffer and written back, up to `COPY_CHUNK_SIZE` bytes at a time.
+    pub async fn copy_blocks(
+        &self,
+        io_channel: &BdevIoChannel<'_>,
+        dst_offset_blocks: u64,
+        src_offset_blocks: u64,
+        num_blocks: u64,
+    ) -> Result<()> {
+        // no buffer to check
+        self.check_blocks(src_offset_blocks, num_blocks, usize::MAX)?;
+        self.check_blocks(dst_offset_blocks, num_blocks, usize::MAX)?;
+        let bdev = self.get_bdev()?;
+        if bdev.io_type_supported(IoType::Copy) {
+            return self
+                .submit_abortable(io_channel, |arg| unsafe {
+                    spdk_bdev_copy_blocks(
+                        self.ptr,
+                        io_channel.ptr,
+                        dst_offset_blocks,
+                        src_offset_blocks,
+                        num_blocks,
+                        Some(callback),
+                        arg,
+                    )
+                })
+                .await;
+        }
+        if num_blocks == 0 {
+            return Ok(());
+        }
+        let block_size = bdev.get_block_size() as u64;
+        let chunk_blocks = (COPY_CHUNK_SIZE / block_size).clamp(1, num_blocks);
+        let mut buf = env::DmaBuf::new((chunk_blocks * block_size) as usize, bdev.get_buf_align())?;
+        // copy from the end if the destination overlaps the tail of the source
+        let backward = dst_offset_blocks > src_offset_blocks
+            && dst_offset_blocks - src_offset_blocks < num_blocks;
+        let mut copied = 0;
+        while copied < num_blocks {
+            let n = chunk_blocks.min(num_blocks - copied);
+            let offset = if backward {
+                num_blocks - copied - n
+            } else {
+                copied
+            };
+            if n < chunk_blocks {
+                buf = env::DmaBuf::new((n * block_size) as usize, bdev.get_buf_align())?;
+            }
+            // the buffer is owned by the I/O, so the future can be dropped
+            buf = self
+                .read_blocks_owned(io_channel, src_offset_blocks + offset, buf)
+                .await?;
+            buf = self
+                .write_blocks_owned(io_channel, dst_offset_blocks + offset, buf)
+                .await?;
+            copied += n;
+        }
+        Ok(())
+    }
+
+    /// find the first block at or after `offset_blocks` that holds data
+    ///
+    /// Return `None` if there is no data after `offset_blocks`.
+    /// Bdevs that don't track allocation report every block as data.
+    pub async fn seek_data(
+        &self,
+        io_channel: &BdevIoChannel<'_>,
+        offset_blocks: u64,
+    ) -> Result<Option<u64>> {
+        let offset: u64 = self
+            .submit_abortable(io_channel, |arg| unsafe {
+                spdk_bdev_seek_data(
+                    self.ptr,
+                    io_channel.ptr,
+                    offset_blocks,
+                    Some(seek_callback),
+                    arg,
+                )
+            })
+            .await?;
+        Ok(Some(offset).filter(|&offset| offset != u64::MAX))
+    }
+
+    /// find the first unallocated block at or after `offset_blocks`
+    ///
+    /// Return `None` if there is no hole after `offset_blocks`.
+    /// Bdevs that don't track allocation have no holes.
+    pub async fn seek_hole(
+        &self,
+        io_channel: &BdevIoChannel<'_>,
+        offset_blocks: u64,
+    ) -> Result<Option<u64>> {
+        let offset: u64 = self
+            .submit_abortable(io_channel, |arg| unsafe {
+                spdk_bdev_seek_hole(
+                    self.ptr,
+                    io_channel.ptr,
+                    offset_blocks,
+                    Some(seek_callback),
+                    arg,
+                )
+            })
+            .await?;
+        Ok(Some(offset).filter(|&offset| offset != u64::MAX))
+    }
+
+    /// Get the ranges of allocated blocks in order, found with `seek_data` and `seek_hole`.
+    ///
+    /// The stream ends after the first error.
+    pub fn extents<'a>(
+        &'a self,
+        io_channel: &'a BdevIoChannel<'a>,
+    ) -> impl Stream<Item = Result<Range<u64>>> + 'a {
+        futures_lite::stream::unfold(Some(0), move |offset| async move {
+            let num_blocks = match self.get_bdev() {
+                Ok(bdev) => bdev.num_blocks(),
+                Err(e) => return Some((Err(e), None)),
+            };
+            let start = match self.seek_data(io_channel, offset?).await {
+                Ok(Some(start)) if start < num_blocks => start,
+                Ok(_) => return None,
+                Err(e) => return Some((Err(e), None)),
+            };
+            let end = match self.seek_hole(io_channel, start).await {
+                Ok(end) => end.map_or(num_blocks, |end| end.clamp(start + 1, num_blocks)),
+                Err(e) => return Some((Err(e), None)),
+            };
+            Some((Ok(start..end), Some(end).filter(|&end| end < num_blocks)))
+        })
+    }
+
+    /// reset the bdev, outstanding I/O on all channels are aborted
+    ///
+    /// spdk_bdev_reset return 0 for success
+    pub async fn reset(&self, io_channel: &BdevIoChannel<'_>) -> Result<()> {
+        self.check_io_type(IoType::Reset)?;
+        self.submit_abortable(io_channel, |arg| unsafe {
+            spdk_bdev_reset(self.ptr, io_channel.ptr, Some(callback), arg)
+        })
+        .await
+    }
+
+    /// compare the data in a list of buffers with the blocks on the bdev
+    ///
+    /// Return `Miscompare` if the data does not match, and `InvalidIo` if the blocks are
+    /// out of range or the buffers are shorter than `num_blocks`.
+    pub async fn compare(
+        &self,
+        io_channel: &BdevIoChannel<'_>,
+        offset_blocks: u64,
+        num_blocks: u64,
+        bufs: &[DmaBuf],
+    ) -> Result<()> {
+        let len = bufs.iter().map(|buf| buf.length).sum::<usize>();
+        self.check_blocks(offset_blocks, num_blocks, len)?;
+        let iovs = to_iovecs(bufs);
+        self.submit_keeping(io_channel, false, iovs, |iovs, arg| unsafe {
+            spdk_bdev_comparev_blocks(
+                self.ptr,
+                io_channel.ptr,
+                iovs.as_mut_ptr(),
+                iovs.len() as i32,
+                offset_blocks,
+                num_blocks,
+                Some(callback),
+                arg,
+            )
+        })
+        .await
+        .0
+    }
+
+    /// atomically compare the blocks with `compare_bufs` and write `write_bufs` if they match
+    ///
+    /// Return `Miscompare` and leave the blocks untouched if the data does not match.
+    /// The blocks and buffers are validated the same way as in `compare`.
+    pub async fn compare_and_write(
+        &self,
+        io_channel: &BdevIoChannel<'_>,
+        offset_blocks: u64,
+        num_blocks: u64,
+        compare_bufs: &[DmaBuf],
+        write_bufs: &[DmaBuf],
+    ) -> Result<()> {
+        for bufs in [compare_bufs, write_bufs] {
+            let len = bufs.iter().map(|buf| buf.length).sum::<usize>();
+            self.check_blocks(offset_blocks, num_blocks, len)?;
+        }
+        let iovs = (to_iovecs(compare_bufs), to_iovecs(write_bufs));
+        self.submit_keeping(io_channel, false, iovs, |iovs, arg| unsafe {
+            spdk_bdev_comparev_and_writev_blocks(
+                self.ptr,
+                io_channel.ptr,
+                iovs.0.as_mut_ptr(),
+                iovs.0.len() as i32,
+                iovs.1.as_mut_ptr(),
+                iovs.1.len() as i32,
+                offset_blocks,
+                num_blocks,
+                Some(callback),
+                arg,
+            )
+        })
+        .await
+        .0
+    }
+}
+
+/// Type of claim on a bdev.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ClaimType {
+    /// Only the claiming descriptor may write, requires a writable descriptor.
+    ExclusiveWrite,
+    /// Any number of readers, the claiming descriptor is the only writer.
+    ReadManyWriteOne,
+    /// Any number of readers and no writer, requires a read-only descriptor.
+    ReadManyWriteNone,
+    /// Any number of readers, writers must claim with the same shared key.
+    ReadManyWriteShared(u64),
+}
+
+impl From<ClaimType> for spdk_bdev_claim_type {
+    fn from(claim_type: ClaimType) -> Self {
+        match claim_type {
+            ClaimType::ExclusiveWrite => spdk_bdev_claim_type_SPDK_BDEV_CLAIM_EXCL_WRITE,
+            ClaimType::ReadManyWriteOne => spdk_bdev_claim_type_SPDK_BDEV_CLAIM_READ_MANY_WRITE_ONE,
+            ClaimType::ReadManyWriteNone => {
+                spdk_bdev_claim_type_SPDK_BDEV_CLAIM_READ_MANY_WRITE_NONE
+            }
+            ClaimType::ReadManyWriteShared(_) => {
+                spdk_bdev_claim_type_SPDK_BDEV_CLAIM_READ_MANY_WRITE_SHARED
+            }
+        }
+    }
+}
+
+/// The bdev module that claims are taken on behalf of.
+fn claim_module() -> *mut spdk_bdev_module {
+    module_by_name("async_spdk")
+}
+
+/// NVMe command for passthrough.
+///
+/// The data and metadata pointers are filled in by the bdev layer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct NvmeCmd {
+    /// Opcode
+    pub opc: u8,
+    /// Namespace identifier
+    pub nsid: u32,
+    pub cdw10: u32,
+    pub cdw11: u32,
+    pub cdw12: u32,
+    pub cdw13: u32,
+    pub cdw14: u32,
+    pub cdw15: u32,
+}
+
+impl NvmeCmd {
+    /// Size of the data returned by an Identify command.
+    pub const IDENTIFY_DATA_SIZE: usize = 4096;
+
+    /// Identify the controller.
+    pub fn identify_controller() -> Self {
+        NvmeCmd {
+            opc: spdk_nvme_admin_opcode_SPDK_NVME_OPC_IDENTIFY as u8,
+            cdw10: spdk_nvme_identify_cns_SPDK_NVME_IDENTIFY_CTRLR,
+            ..Default::default()
+        }
+    }
+
+    /// Identify the namespace `nsid`.
+    pub fn identify_namespace(nsid: u32) -> Self {
+        NvmeCmd {
+            opc: spdk_nvme_admin_opcode_SPDK_NVME_OPC_IDENTIFY as u8,
+            nsid,
+            cdw10: spdk_nvme_identify_cns_SPDK_NVME_IDENTIFY_NS,
+            ..Default::default()
+        }
     }
 
-    pub fn release_io_channel(&self, ioc: IoChannel) {
-        unsafe {
-            spdk_put_io_channel(ioc.ptr);
+    fn to_raw(self) -> spdk_nvme_cmd {
+        // a submission queue entry is 16 dwords, with opcode in the lowest byte of dword 0
+        let mut dwords = [0u32; 16];
+        dwords[0] = self.opc as u32;
+        dwords[1] = self.nsid;
+        dwords[10] = self.cdw10;
+        dwords[11] = self.cdw11;
+        dwords[12] = self.cdw12;
+        dwords[13] = self.cdw13;
+        dwords[14] = self.cdw14;
+        dwords[15] = self.cdw15;
+        unsafe { std::mem::transmute::<[u32; 16], spdk_nvme_cmd>(dwords) }
+    }
+}
+
+/// Completion of a successful NVMe passthrough command.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct NvmeCompletion {
+    /// Command specific dword 0
+    pub cdw0: u32,
+    /// Status code type
+    pub sct: i32,
+    /// Status code
+    pub sc: i32,
+}
+
+/// Event on a bdev that has been opened.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum BdevEvent {
+    /// The bdev is being removed, in-flight I/O should be drained and the descriptor closed.
+    Remove,
+    /// The size of the bdev has changed.
+    Resize,
+    /// Media management event from the device.
+    MediaManagement,
+}
+
+/// Events received on a descriptor that are not consumed yet.
+#[derive(Debug)]
+pub(crate) struct EventQueue<T = BdevEvent> {
+    events: VecDeque<T>,
+    waker: Option<Waker>,
+    closed: bool,
+    /// A stream is consuming the events.
+    taken: bool,
+}
+
+impl<T> Default for EventQueue<T> {
+    fn default() -> Self {
+        EventQueue {
+            events: VecDeque::new(),
+            waker: None,
+            closed: false,
+            taken: false,
+        }
+    }
+}
+
+impl<T> EventQueue<T> {
+    fn push(&mut self, event: T) {
+        self.events.push_back(event);
+        if let Some(waker) = self.waker.take() {
+            waker.wake();
+        }
+    }
+
+    /// No more events will be received.
+    pub(crate) fn close(&mut self) {
+        self.closed = true;
+        if let Some(waker) = self.waker.take() {
+            waker.wake();
         }
     }
 }
 
-/// Bdev
+/// Stream of events on an opened bdev.
 #[derive(Debug)]
-pub struct BdevDesc {
-    ptr: *mut spdk_bdev_desc,
+pub struct BdevEventStream {
+    queue: Rc<RefCell<EventQueue>>,
 }
 
-impl BdevDesc {
-    pub fn create_desc(name: &str) -> Result<Self> {
-        let cname = CString::new(name).expect("Could not parse to CString");
-        let mut ptr = MaybeUninit::uninit();
-        extern "C" fn callback(
-            ty: spdk_bdev_event_type,
-            bdev: *mut spdk_bdev,
-            event_ctx: *mut c_void,
-        ) {
-            warn!(
-                "bdev callback: type = {:?}, bdev={:?}, ctx={:?}",
-                ty, bdev, event_ctx
-            );
+impl BdevEventStream {
+    /// Take the stream of the queue, or `None` if another stream has it.
+    pub(crate) fn take(queue: &Rc<RefCell<EventQueue>>) -> Option<Self> {
+        let mut q = queue.borrow_mut();
+        if q.taken {
+            return None;
         }
-        let err = unsafe {
-            spdk_bdev_open_ext(
-                cname.as_ptr(),
-                true,
-                Some(callback),
-                std::ptr::null_mut(),
-                ptr.as_mut_ptr(),
-            )
-        };
-        SpdkError::from_retval(err)?;
-        Ok(BdevDesc {
-            ptr: unsafe { ptr.assume_init() },
+        q.taken = true;
+        Some(BdevEventStream {
+            queue: queue.clone(),
         })
     }
+}
 
-    pub fn get_bdev(&self) -> Result<BDev> {
-        let ptr = unsafe { spdk_bdev_desc_get_bdev(self.ptr) };
-        if ptr.is_null() {
-            return Err(SpdkError::from(-1));
+impl Drop for BdevEventStream {
+    fn drop(&mut self) {
+        self.queue.borrow_mut().taken = false;
+    }
+}
+
+impl Stream for BdevEventStream {
+    type Item = BdevEvent;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let mut queue = self.queue.borrow_mut();
+        if let Some(event) = queue.events.pop_front() {
+            return Poll::Ready(Some(event));
         }
-        Ok(BDev { ptr })
+        if queue.closed {
+            return Poll::Ready(None);
+        }
+        queue.waker = Some(cx.waker().clone());
+        Poll::Pending
     }
+}
 
-    pub fn get_io_channel(&self) -> Result<IoChannel> {
-        let ptr = unsafe { spdk_bdev_get_io_channel(self.ptr) };
-        if ptr.is_null() {
-            return Err(SpdkError::from(-1));
+/// I/O submitted through a descriptor that has not completed within its timeout.
+#[derive(Debug, Clone)]
+pub struct TimedOutIo {
+    /// I/O type, or `None` if it is unknown to this crate.
+    pub io_type: Option<IoType>,
+    /// First block of a block-addressed I/O
+    pub offset_blocks: u64,
+    /// Number of blocks of a block-addressed I/O
+    pub num_blocks: u64,
+    channel: *mut spdk_io_channel,
+    caller_ctx: *mut c_void,
+    /// Generation of the completion at `caller_ctx`, `None` if the I/O was not submitted
+    /// by a future of the descriptor.
+    generation: Option<u64>,
+}
+
+/// Stream of the I/O that have timed out on a descriptor.
+#[derive(Debug)]
+pub struct TimeoutStream {
+    queue: Rc<RefCell<EventQueue<TimedOutIo>>>,
+}
+
+impl Stream for TimeoutStream {
+    type Item = TimedOutIo;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let mut queue = self.queue.borrow_mut();
+        if let Some(io) = queue.events.pop_front() {
+            return Poll::Ready(Some(io));
         }
-        Ok(IoChannel { ptr })
+        if queue.closed {
+            return Poll::Ready(None);
+        }
+        queue.waker = Some(cx.waker().clone());
+        Poll::Pending
     }
+}
+
+/// Timeout callback of a descriptor.
+///
+/// `cb_arg` must point to the `RefCell<EventQueue<TimedOutIo>>` of the descriptor.
+extern "C" fn timeout_callback(cb_arg: *mut c_void, bio: *mut spdk_bdev_io) {
+    let caller_ctx = unsafe { spdk_bdev_io_get_cb_arg(bio) };
+    let io = TimedOutIo {
+        io_type: IoType::from_raw(unsafe { (*bio).type_ } as spdk_bdev_io_type),
+        offset_blocks: unsafe { (*bio).u.bdev.offset_blocks },
+        num_blocks: unsafe { (*bio).u.bdev.num_blocks },
+        channel: unsafe { spdk_bdev_io_get_io_channel(bio) },
+        caller_ctx,
+        generation: generation_of(caller_ctx),
+    };
+    let queue = unsafe { &*(cb_arg as *const RefCell<EventQueue<TimedOutIo>>) };
+    queue.borrow_mut().push(io);
+}
+
+/// Event callback for opening a bdev.
+///
+/// `event_ctx` must point to the `RefCell<EventQueue>` of the opener.
+#[allow(non_upper_case_globals)]
+pub(crate) extern "C" fn event_callback(
+    ty: spdk_bdev_event_type,
+    _bdev: *mut spdk_bdev,
+    event_ctx: *mut c_void,
+) {
+    let event = match ty {
+        spdk_bdev_event_type_SPDK_BDEV_EVENT_REMOVE => BdevEvent::Remove,
+        spdk_bdev_event_type_SPDK_BDEV_EVENT_RESIZE => BdevEvent::Resize,
+        spdk_bdev_event_type_SPDK_BDEV_EVENT_MEDIA_MANAGEMENT => BdevEvent::MediaManagement,
+        _ => return,
+    };
+    let queue = unsafe { &*(event_ctx as *const RefCell<EventQueue>) };
+    queue.borrow_mut().push(event);
+}
+
+/// Completion status of a failed bdev I/O.
+///
+/// The bdev layer translates the status of every I/O into both NVMe and SCSI
+/// representation, so both of them are available regardless of the backend.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct IoStatus {
+    /// NVMe completion queue entry dword 0
+    pub cdw0: u32,
+    /// NVMe status code type
+    pub sct: i32,
+    /// NVMe status code
+    pub sc: i32,
+    /// SCSI status code
+    pub scsi_status: i32,
+    /// SCSI sense key
+    pub sense_key: i32,
+    /// SCSI additional sense code
+    pub asc: i32,
+    /// SCSI additional sense code qualifier
+    pub ascq: i32,
+}
 
-    pub fn close(&self) {
+impl IoStatus {
+    fn from_bdev_io(bio: *const spdk_bdev_io) -> Self {
+        let mut status = IoStatus::default();
         unsafe {
-            spdk_bdev_close(self.ptr);
+            spdk_bdev_io_get_nvme_status(bio, &mut status.cdw0, &mut status.sct, &mut status.sc);
+            spdk_bdev_io_get_scsi_status(
+                bio,
+                &mut status.scsi_status,
+                &mut status.sense_key,
+                &mut status.asc,
+                &mut status.ascq,
+            );
         }
+        status
     }
 
-    /// write data at offset
-    ///
-    /// TODO: check write buffer size and handle return value
-    ///
-    /// spdk_bdev_write return 0 for success
-    pub async fn write(
-        &self,
-        io_channel: &IoChannel,
-        offset: u64,
-        length: u64,
-        buf: &[u8],
-    ) -> Result<()> {
-        do_async(|arg| unsafe {
-            spdk_bdev_write(
-                self.ptr,
-                io_channel.ptr,
-                buf.as_ptr() as _,
-                offset,
-                length,
-                Some(callback),
-                arg,
-            );
-        })
-        .await
+    /// The data did not match in a compare command.
+    pub fn is_miscompare(&self) -> bool {
+        self.sct == spdk_nvme_status_code_type_SPDK_NVME_SCT_MEDIA_ERROR as i32
+            && self.sc == spdk_nvme_media_error_status_code_SPDK_NVME_SC_COMPARE_FAILURE as i32
     }
 
-    /// read data at offset
-    ///
-    /// TODO: handle return value (should not be ())
-    ///
-    /// spdk_bdev_read return 0 for success
-    pub async fn read(
-        &self,
-        io_channel: &IoChannel,
-        offset: u64,
-        length: u64,
-        buf: &mut [u8],
-    ) -> Result<()> {
-        do_async(|arg| unsafe {
-            spdk_bdev_read(
-                self.ptr,
-                io_channel.ptr,
-                buf.as_mut_ptr() as _,
-                offset,
-                length,
-                Some(callback),
-                arg,
-            );
-        })
-        .await
+    /// The I/O failed because of a media or data integrity error.
+    pub fn is_media_error(&self) -> bool {
+        self.sct == spdk_nvme_status_code_type_SPDK_NVME_SCT_MEDIA_ERROR as i32
+    }
+
+    /// The I/O was aborted before completion.
+    pub fn is_aborted(&self) -> bool {
+        self.sct == spdk_nvme_status_code_type_SPDK_NVME_SCT_GENERIC as i32
+            && (self.sc
+                == spdk_nvme_generic_command_status_code_SPDK_NVME_SC_ABORTED_BY_REQUEST as i32
+                || self.sc
+                    == spdk_nvme_generic_command_status_code_SPDK_NVME_SC_ABORTED_SQ_DELETION
+                        as i32)
+    }
+}
+
+impl fmt::Display for IoStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "sct={:#x}, sc={:#x}, sense_key={:#x}, asc={:#x}, ascq={:#x}",
+            self.sct, self.sc, self.sense_key, self.asc, self.ascq
+        )
+    }
+}
+
+thread_local! {
+    /// Completions of the I/O on this thread whose futures have been dropped, with what
+    /// the I/O uses until it completes.
+    static ABANDONED: RefCell<HashMap<usize, Box<dyn Any>>> = RefCell::new(HashMap::new());
+    /// Generations of the completions of the I/O in flight on this thread.
+    static IN_FLIGHT: RefCell<HashMap<usize, u64>> = RefCell::new(HashMap::new());
+    static NEXT_GENERATION: Cell<u64> = const { Cell::new(0) };
+}
+
+/// Completion of an I/O submitted through a descriptor.
+///
+/// It is boxed so that the completion callback can still run after the future is dropped,
+/// and `keep` holds what the I/O uses until then, such as an owned buffer or an iovec
+/// array. Dropping it before the I/O completes leaves the completion and `keep` to be
+/// freed by the callback, and aborts the I/O if `abort_on_drop` is set.
+struct InFlight<T, K: 'static> {
+    complete: *mut LocalComplete<Result<T>>,
+    desc: *mut spdk_bdev_desc,
+    channel: *mut spdk_io_channel,
+    submitted: bool,
+    abort_on_drop: bool,
+    keep: Option<K>,
+}
+
+impl<T, K: 'static> InFlight<T, K> {
+    fn new(
+        desc: *mut spdk_bdev_desc,
+        channel: *mut spdk_io_channel,
+        abort_on_drop: bool,
+        keep: K,
+    ) -> Self {
+        InFlight {
+            complete: Box::into_raw(Box::new(LocalComplete::new())),
+            desc,
+            channel,
+            submitted: false,
+            abort_on_drop,
+            keep: Some(keep),
+        }
+    }
+
+    fn as_arg(&mut self) -> *mut c_void {
+        self.complete as *mut c_void
+    }
+
+    /// Mark the I/O as submitted, so that it can be aborted after a timeout.
+    fn set_submitted(&mut self) {
+        self.submitted = true;
+        let generation = NEXT_GENERATION.with(|next| next.replace(next.get() + 1));
+        IN_FLIGHT.with(|in_flight| {
+            in_flight
+                .borrow_mut()
+                .insert(self.complete as usize, generation)
+        });
+    }
+}
+
+impl<T: Unpin, K: Unpin + 'static> Future for InFlight<T, K> {
+    type Output = Result<T>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let complete = unsafe { &mut *self.complete };
+        let poll = Pin::new(complete).poll(cx);
+        if poll.is_ready() {
+            self.submitted = false;
+        }
+        poll
+    }
+}
+
+impl<T, K: 'static> Drop for InFlight<T, K> {
+    fn drop(&mut self) {
+        if !self.submitted || unsafe { (*self.complete).is_complete() } {
+            drop(unsafe { Box::from_raw(self.complete) });
+            return;
+        }
+        let keep: Box<dyn Any> = Box::new(self.keep.take());
+        ABANDONED.with(|abandoned| abandoned.borrow_mut().insert(self.complete as usize, keep));
+        if !self.abort_on_drop {
+            warn!("bdev I/O with borrowed buffers dropped before completion");
+            return;
+        }
+        let rc = unsafe {
+            spdk_bdev_abort(
+                self.desc,
+                self.channel,
+                self.as_arg(),
+                Some(abort_callback),
+                std::ptr::null_mut(),
+            )
+        };
+        if rc != 0 {
+            debug!("failed to abort dropped bdev I/O: {}", rc);
+        }
     }
 }
 
-#[warn(dead_code)]
+/// Wait on the I/O channel for a free `spdk_bdev_io`.
+///
+/// The entry is queued on the heap, so the future can be dropped while waiting,
+/// and the entry is then freed by the callback.
 #[derive(Debug)]
 pub struct IoWaitEntry {
+    ctx: *mut IoWaitCtx,
+}
+
+/// Entry queued on the channel, with the state shared with the callback.
+#[repr(C)]
+struct IoWaitCtx {
     wentry: spdk_bdev_io_wait_entry,
+    waker: Option<Waker>,
+    /// The callback has run, or the entry was not queued.
+    done: bool,
+    /// The future has been dropped, the callback frees the entry.
+    abandoned: bool,
+}
+
+impl IoWaitEntry {
+    /// Queue an entry to wait until a `spdk_bdev_io` is available on the I/O channel.
+    ///
+    /// Should only be called after a submission on the channel returned `-ENOMEM`.
+    pub(crate) fn new(bdev: *mut spdk_bdev, ch: *mut spdk_io_channel) -> Self {
+        let ctx = Box::into_raw(Box::new(IoWaitCtx {
+            wentry: unsafe { std::mem::zeroed() },
+            waker: None,
+            done: false,
+            abandoned: false,
+        }));
+        unsafe {
+            (*ctx).wentry.bdev = bdev;
+            (*ctx).wentry.cb_fn = Some(io_wait_callback);
+            (*ctx).wentry.cb_arg = ctx as *mut c_void;
+            if spdk_bdev_queue_io_wait(bdev, ch, &mut (*ctx).wentry) != 0 {
+                // the bdev_io pool is no longer exhausted, retry immediately
+                (*ctx).done = true;
+            }
+        }
+        IoWaitEntry { ctx }
+    }
+}
+
+impl Future for IoWaitEntry {
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let ptr = self.ctx;
+        let ctx = unsafe { &mut *ptr };
+        if ctx.done {
+            return Poll::Ready(());
+        }
+        ctx.waker = Some(cx.waker().clone());
+        Poll::Pending
+    }
+}
+
+impl Drop for IoWaitEntry {
+    fn drop(&mut self) {
+        unsafe {
+            if (*self.ctx).done {
+                drop(Box::from_raw(self.ctx));
+            } else {
+                // still linked in the io_wait queue of the channel
+                (*self.ctx).abandoned = true;
+            }
+        }
+    }
 }
 
+/// I/O submitted to a bdev implemented by a `BdevModule`.
+///
+/// It must be completed on the thread it was submitted on, but may be held across awaits.
+/// Dropping it without calling `complete` fails the I/O.
+///
+/// [`BdevModule`]: crate::bdev_module::BdevModule
 #[derive(Debug)]
 pub struct BdevIo {
-    ptr: *mut spdk_bdev_io,
+    pub(crate) ptr: *mut spdk_bdev_io,
 }
 
 impl BdevIo {
-    pub fn free_io(&self) {
-        unsafe { spdk_bdev_free_io(self.ptr) };
+    /// Get the bdev this I/O is submitted to.
+    pub fn bdev(&self) -> BDev {
+        BDev {
+            ptr: unsafe { (*self.ptr).bdev },
+        }
+    }
+
+    /// Get the I/O type, or `None` if it is unknown to this crate.
+    pub fn io_type(&self) -> Option<IoType> {
+        IoType::from_raw(unsafe { (*self.ptr).type_ } as spdk_bdev_io_type)
+    }
+
+    /// Get the first block of a block-addressed I/O.
+    pub fn offset_blocks(&self) -> u64 {
+        unsafe { (*self.ptr).u.bdev.offset_blocks }
+    }
+
+    /// Get the number of blocks of a block-addressed I/O.
+    pub fn num_blocks(&self) -> u64 {
+        unsafe { (*self.ptr).u.bdev.num_blocks }
+    }
+
+    /// Get the data buffers of a read or write.
+    pub fn buffers(&self) -> Vec<&[u8]> {
+        self.iovs()
+            .iter()
+            .map(|iov| unsafe { from_raw_parts(iov.iov_base as *const u8, iov.iov_len) })
+            .collect()
+    }
+
+    /// Get the data buffers of a read or write to fill.
+    pub fn buffers_mut(&mut self) -> Vec<&mut [u8]> {
+        self.iovs()
+            .iter()
+            .map(|iov| unsafe { from_raw_parts_mut(iov.iov_base as *mut u8, iov.iov_len) })
+            .collect()
+    }
+
+    /// Gather the data of a write into `buf`, return the number of bytes copied.
+    pub fn copy_to(&self, buf: &mut [u8]) -> usize {
+        let mut len = 0;
+        for src in self.buffers() {
+            let n = src.len().min(buf.len() - len);
+            buf[len..len + n].copy_from_slice(&src[..n]);
+            len += n;
+        }
+        len
+    }
+
+    /// Scatter `buf` into the buffers of a read, return the number of bytes copied.
+    pub fn copy_from(&mut self, buf: &[u8]) -> usize {
+        let mut len = 0;
+        for dst in self.buffers_mut() {
+            let n = dst.len().min(buf.len() - len);
+            dst[..n].copy_from_slice(&buf[len..len + n]);
+            len += n;
+        }
+        len
+    }
+
+    fn iovs(&self) -> &[iovec] {
+        let bdev = unsafe { &(*self.ptr).u.bdev };
+        if bdev.iovs.is_null() {
+            return &[];
+        }
+        unsafe { from_raw_parts(bdev.iovs, bdev.iovcnt as usize) }
+    }
+
+    /// Complete the I/O with the result.
+    ///
+    /// `IoFailed` is reported with its NVMe status, and `-ENOMEM` makes the bdev layer
+    /// retry the I/O later.
+    pub fn complete(mut self, result: Result<()>) {
+        let ptr = std::mem::replace(&mut self.ptr, std::ptr::null_mut());
+        let status = match result {
+            Ok(()) => spdk_bdev_io_status_SPDK_BDEV_IO_STATUS_SUCCESS,
+            Err(SpdkError::Miscompare) => spdk_bdev_io_status_SPDK_BDEV_IO_STATUS_MISCOMPARE,
+            Err(SpdkError::IoFailed(status)) if status.sct != 0 || status.sc != 0 => {
+                unsafe {
+                    spdk_bdev_io_complete_nvme_status(ptr, status.cdw0, status.sct, status.sc)
+                };
+                return;
+            }
+            Err(SpdkError::Errno { errno, .. }) if errno == -(ENOMEM as i32) => {
+                spdk_bdev_io_status_SPDK_BDEV_IO_STATUS_NOMEM
+            }
+            Err(_) => spdk_bdev_io_status_SPDK_BDEV_IO_STATUS_FAILED,
+        };
+        unsafe { spdk_bdev_io_complete(ptr, status) };
+    }
+}
+
+impl Drop for BdevIo {
+    fn drop(&mut self) {
+        if !self.ptr.is_null() {
+            warn!("bdev I/O dropped without completion");
+            unsafe {
+                spdk_bdev_io_complete(self.ptr, spdk_bdev_io_status_SPDK_BDEV_IO_STATUS_FAILED)
+            };
+        }
+    }
+}
+
+/// Data read into buffers supplied by the bdev layer.
+///
+/// The `spdk_bdev_io` holding the buffers is freed when the guard is dropped.
+#[derive(Debug)]
+pub struct ReadGuard<'a> {
+    bio: *mut spdk_bdev_io,
+    _channel: PhantomData<&'a BdevIoChannel<'a>>,
+}
+
+impl ReadGuard<'_> {
+    /// Get the buffers holding the data, usually only one.
+    pub fn buffers(&self) -> Vec<&[u8]> {
+        let mut iovs = std::ptr::null_mut();
+        let mut iovcnt = 0;
+        unsafe { spdk_bdev_io_get_iovec(self.bio, &mut iovs, &mut iovcnt) };
+        if iovs.is_null() {
+            return vec![];
+        }
+        unsafe { from_raw_parts(iovs, iovcnt as usize) }
+            .iter()
+            .map(|iov| unsafe { from_raw_parts(iov.iov_base as *const u8, iov.iov_len) })
+            .collect()
+    }
+
+    /// Get the length of the data in bytes.
+    pub fn len(&self) -> usize {
+        self.buffers().iter().map(|buf| buf.len()).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Copy the data into `buf`, return the number of bytes copied.
+    pub fn copy_to(&self, buf: &mut [u8]) -> usize {
+        let mut len = 0;
+        for src in self.buffers() {
+            let n = src.len().min(buf.len() - len);
+            buf[len..len + n].copy_from_slice(&src[..n]);
+            len += n;
+        }
+        len
+    }
+}
+
+impl Drop for ReadGuard<'_> {
+    fn drop(&mut self) {
+        unsafe { spdk_bdev_free_io(self.bio) };
     }
 }
 
@@ -252,21 +2564,151 @@ impl Drop for DmaBuf {
     }
 }
 
+/// Copy a C string owned by SPDK.
+unsafe fn to_string(ptr: *const c_char) -> String {
+    CStr::from_ptr(ptr).to_string_lossy().into_owned()
+}
+
+/// Return `InvalidIo` if a buffer of `len` bytes can not hold `length` bytes.
+fn check_buf_len(len: usize, length: u64) -> Result<()> {
+    if length > len as u64 {
+        return Err(SpdkError::InvalidIo(format!(
+            "length {} exceeds the buffer of {} bytes",
+            length, len
+        )));
+    }
+    Ok(())
+}
+
+/// Describe a list of DMA buffers as an iovec array.
+fn to_iovecs(bufs: &[DmaBuf]) -> Vec<iovec> {
+    bufs.iter()
+        .map(|buf| iovec {
+            iov_base: buf.buf,
+            iov_len: buf.length,
+        })
+        .collect()
+}
+
 extern "C" fn callback(bio: *mut spdk_bdev_io, s: bool, arg: *mut c_void) {
     callback_with(arg, (), s, bio);
 }
 
+extern "C" fn append_callback(bio: *mut spdk_bdev_io, s: bool, arg: *mut c_void) {
+    let lba = unsafe { spdk_bdev_io_get_append_location(bio) };
+    callback_with(arg, lba, s, bio);
+}
+
+extern "C" fn seek_callback(bio: *mut spdk_bdev_io, s: bool, arg: *mut c_void) {
+    let offset = unsafe { spdk_bdev_io_get_seek_offset(bio) };
+    callback_with(arg, offset, s, bio);
+}
+
+extern "C" fn nvme_callback(bio: *mut spdk_bdev_io, s: bool, arg: *mut c_void) {
+    let status = IoStatus::from_bdev_io(bio);
+    let completion = NvmeCompletion {
+        cdw0: status.cdw0,
+        sct: status.sct,
+        sc: status.sc,
+    };
+    callback_with(arg, completion, s, bio);
+}
+
 extern "C" fn callback_with<T>(arg: *mut c_void, bs: T, s: bool, bio: *mut spdk_bdev_io) {
+    if release_abandoned::<T>(arg, bio) {
+        return;
+    }
     let complete = unsafe { &mut *(arg as *mut LocalComplete<Result<T>>) };
 
-    let result = if !s { Err(SpdkError::from(-1)) } else { Ok(bs) };
+    // the status must be captured before the bdev_io is freed
+    let result = if !s { Err(io_error(bio)) } else { Ok(bs) };
     complete.complete(result);
     unsafe {
         spdk_bdev_free_io(bio);
     }
 }
 
-async fn do_async<T: Unpin>(f: impl FnOnce(*mut c_void)) -> Result<T> {
+/// Keep the `spdk_bdev_io` of a successful read for `ReadGuard`.
+extern "C" fn borrowed_callback(bio: *mut spdk_bdev_io, s: bool, arg: *mut c_void) {
+    if release_abandoned::<ReadGuard<'static>>(arg, bio) {
+        return;
+    }
+    let complete = unsafe { &mut *(arg as *mut LocalComplete<Result<ReadGuard<'static>>>) };
+    if s {
+        return complete.complete(Ok(ReadGuard {
+            bio,
+            _channel: PhantomData,
+        }));
+    }
+    let err = io_error(bio);
+    unsafe { spdk_bdev_free_io(bio) };
+    complete.complete(Err(err));
+}
+
+/// Get the generation of the completion of an I/O in flight on this thread.
+fn generation_of(caller_ctx: *mut c_void) -> Option<u64> {
+    IN_FLIGHT.with(|in_flight| in_flight.borrow().get(&(caller_ctx as usize)).copied())
+}
+
+/// Remove a completed I/O from the I/O in flight, and free its completion and
+/// `spdk_bdev_io` if its future has been dropped.
+///
+/// Returns false if the future is still waiting for the completion.
+fn release_abandoned<T>(arg: *mut c_void, bio: *mut spdk_bdev_io) -> bool {
+    IN_FLIGHT.with(|in_flight| in_flight.borrow_mut().remove(&(arg as usize)));
+    let keep = ABANDONED.with(|abandoned| abandoned.borrow_mut().remove(&(arg as usize)));
+    if keep.is_some() {
+        drop(unsafe { Box::from_raw(arg as *mut LocalComplete<Result<T>>) });
+        unsafe { spdk_bdev_free_io(bio) };
+    }
+    keep.is_some()
+}
+
+/// Completion of the abort of an abandoned I/O.
+extern "C" fn abort_callback(bio: *mut spdk_bdev_io, _s: bool, _arg: *mut c_void) {
+    unsafe { spdk_bdev_free_io(bio) };
+}
+
+/// Build the error for a failed bdev I/O from its completion status.
+pub(crate) fn io_error(bio: *mut spdk_bdev_io) -> SpdkError {
+    let status = IoStatus::from_bdev_io(bio);
+    if status.is_miscompare() {
+        SpdkError::Miscompare
+    } else {
+        SpdkError::IoFailed(status)
+    }
+}
+
+extern "C" fn io_wait_callback(arg: *mut c_void) {
+    let ctx = arg as *mut IoWaitCtx;
+    unsafe {
+        if (*ctx).abandoned {
+            return drop(Box::from_raw(ctx));
+        }
+        (*ctx).done = true;
+        if let Some(waker) = (*ctx).waker.take() {
+            waker.wake();
+        }
+    }
+}
+
+extern "C" fn stat_callback(
+    _bdev: *mut spdk_bdev,
+    _stat: *mut spdk_bdev_io_stat,
+    arg: *mut c_void,
+    rc: c_int,
+) {
+    let complete = unsafe { &mut *(arg as *mut LocalComplete<Result<()>>) };
+    complete.complete(SpdkError::from_retval(rc));
+}
+
+extern "C" fn qos_callback(arg: *mut c_void, status: c_int) {
+    let complete = unsafe { &mut *(arg as *mut LocalComplete<Result<()>>) };
+    complete.complete(SpdkError::from_retval(status));
+}
+
+/// Run an SPDK call completing through `LocalComplete` and wait for its result.
+pub(crate) async fn do_async<T: Unpin>(f: impl FnOnce(*mut c_void)) -> Result<T> {
     let complete = LocalComplete::<Result<T>>::new();
     futures_lite::pin!(complete);
     f(complete.as_arg());
@@ -0,0 +1,228 @@
+//! Block I/O submitted in batches
+
+use crate::bdev::{io_error, BdevDesc, BdevIoChannel, IoWaitEntry};
+use crate::env::DmaBuf;
+use crate::{Result, SpdkError};
+use futures_lite::Stream;
+use spdk_sys::*;
+use std::{
+    cell::RefCell,
+    collections::VecDeque,
+    ffi::c_void,
+    future::Future,
+    pin::Pin,
+    rc::Rc,
+    task::{Context, Poll, Waker},
+};
+
+/// Block I/O of a batch, owning its buffer.
+#[derive(Debug)]
+pub enum BatchIo {
+    /// Read the blocks from `offset_blocks` into the whole buffer.
+    Read { offset_blocks: u64, buf: DmaBuf },
+    /// Write the whole buffer to the blocks from `offset_blocks`.
+    Write { offset_blocks: u64, buf: DmaBuf },
+}
+
+/// Completions not yielded yet, shared with the I/O in flight.
+#[derive(Default)]
+struct Completions {
+    done: VecDeque<(BatchIo, Result<()>)>,
+    waker: Option<Waker>,
+}
+
+/// An I/O in flight, passed to the completion callback.
+struct Slot {
+    completions: Rc<RefCell<Completions>>,
+    io: BatchIo,
+}
+
+/// Submit a stream of I/O on one channel, keeping up to `depth` of them in flight.
+///
+/// It is a stream of the I/O with their results in completion order. Requests are only
+/// taken from the input while there are free slots, and no more are submitted while the
+/// bdev layer is out of `spdk_bdev_io`.
+///
+/// If it is dropped, the I/O in flight keep running and their buffers are freed
+/// when they complete, as is the entry waiting for a free `spdk_bdev_io`.
+pub struct BatchSubmitter<'a, S> {
+    desc: &'a BdevDesc,
+    channel: &'a BdevIoChannel<'a>,
+    requests: S,
+    depth: usize,
+    in_flight: usize,
+    /// The input has ended.
+    finished: bool,
+    /// A request that has failed with `-ENOMEM`, submitted again after an I/O completes.
+    retry: Option<BatchIo>,
+    /// Waiting for a free `spdk_bdev_io` while no I/O of this batch is in flight.
+    io_wait: Option<IoWaitEntry>,
+    completions: Rc<RefCell<Completions>>,
+}
+
+impl<'a, S: Stream<Item = BatchIo> + Unpin> BatchSubmitter<'a, S> {
+    /// Create a batch submitting `requests` on `channel`.
+    pub fn new(
+        desc: &'a BdevDesc,
+        channel: &'a BdevIoChannel<'a>,
+        depth: usize,
+        requests: S,
+    ) -> Self {
+        assert!(depth > 0, "queue depth must be positive");
+        BatchSubmitter {
+            desc,
+            channel,
+            requests,
+            depth,
+            in_flight: 0,
+            finished: false,
+            retry: None,
+            io_wait: None,
+            completions: Rc::default(),
+        }
+    }
+
+    /// Get the number of I/O in flight.
+    pub fn in_flight(&self) -> usize {
+        self.in_flight
+    }
+
+    /// Submit requests until the queue is full, the input is pending or the bdev layer
+    /// is out of `spdk_bdev_io`.
+    ///
+    /// Return the result of a request that has failed to submit.
+    fn fill(&mut self, cx: &mut Context<'_>) -> Option<(BatchIo, Result<()>)> {
+        while self.in_flight < self.depth && self.io_wait.is_none() {
+            let io = match self.retry.take() {
+                Some(io) => io,
+                None if self.finished => return None,
+                None => match Pin::new(&mut self.requests).poll_next(cx) {
+                    Poll::Ready(Some(io)) => io,
+                    Poll::Ready(None) => {
+                        self.finished = true;
+                        return None;
+                    }
+                    Poll::Pending => return None,
+                },
+            };
+            match self.submit(io) {
+                Ok(()) => self.in_flight += 1,
+                Err((io, SpdkError::Errno { errno, .. })) if errno == -(ENOMEM as i32) => {
+                    self.retry = Some(io);
+                    if self.in_flight > 0 {
+                        // retry when an I/O of this batch completes
+                        return None;
+                    }
+                    let bdev = unsafe { spdk_bdev_desc_get_bdev(self.desc.ptr) };
+                    self.io_wait = Some(IoWaitEntry::new(bdev, self.channel.ptr));
+                }
+                Err((io, e)) => return Some((io, Err(e))),
+            }
+        }
+        None
+    }
+
+    /// Submit an I/O, giving it back with the error if it fails.
+    fn submit(&self, io: BatchIo) -> std::result::Result<(), (BatchIo, SpdkError)> {
+        let (offset_blocks, buf) = match &io {
+            BatchIo::Read { offset_blocks, buf } | BatchIo::Write { offset_blocks, buf } => {
+                (*offset_blocks, buf)
+            }
+        };
+        let num_blocks = match self
+            .desc
+            .check_buf(offset_blocks, buf.as_ptr(), buf.as_ref().len())
+        {
+            Ok(num_blocks) => num_blocks,
+            Err(e) => return Err((io, e)),
+        };
+        let ptr = buf.as_ptr() as *mut c_void;
+        let read = matches!(io, BatchIo::Read { .. });
+        let slot = Box::into_raw(Box::new(Slot {
+            completions: self.completions.clone(),
+            io,
+        }));
+        let rc = unsafe {
+            if read {
+                spdk_bdev_read_blocks(
+                    self.desc.ptr,
+                    self.channel.ptr,
+                    ptr,
+                    offset_blocks,
+                    num_blocks,
+                    Some(batch_callback),
+                    slot as *mut c_void,
+                )
+            } else {
+                spdk_bdev_write_blocks(
+                    self.desc.ptr,
+                    self.channel.ptr,
+                    ptr,
+                    offset_blocks,
+                    num_blocks,
+                    Some(batch_callback),
+                    slot as *mut c_void,
+                )
+            }
+        };
+        if rc != 0 {
+            let slot = unsafe { Box::from_raw(slot) };
+            return Err((slot.io, SpdkError::from(rc)));
+        }
+        Ok(())
+    }
+}
+
+impl<S: Stream<Item = BatchIo> + Unpin> Stream for BatchSubmitter<'_, S> {
+    type Item = (BatchIo, Result<()>);
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+        loop {
+            let done = this.completions.borrow_mut().done.pop_front();
+            if done.is_some() {
+                this.in_flight -= 1;
+            }
+            if let Some(io_wait) = &mut this.io_wait {
+                if Pin::new(io_wait).poll(cx).is_ready() {
+                    this.io_wait = None;
+                }
+            }
+            let waiting = this.io_wait.is_some();
+            // refill the queue before handing out the completion
+            if let Some(failed) = this.fill(cx) {
+                if let Some(done) = done {
+                    this.completions.borrow_mut().done.push_front(done);
+                    this.in_flight += 1;
+                }
+                return Poll::Ready(Some(failed));
+            }
+            if let Some(done) = done {
+                return Poll::Ready(Some(done));
+            }
+            if this.in_flight == 0 && this.finished && this.retry.is_none() {
+                return Poll::Ready(None);
+            }
+            if !waiting && this.io_wait.is_some() {
+                // poll the new entry, nothing else wakes the task without I/O in flight
+                continue;
+            }
+            let mut completions = this.completions.borrow_mut();
+            if completions.done.is_empty() {
+                completions.waker = Some(cx.waker().clone());
+                return Poll::Pending;
+            }
+        }
+    }
+}
+
+extern "C" fn batch_callback(bio: *mut spdk_bdev_io, s: bool, arg: *mut c_void) {
+    let Slot { completions, io } = *unsafe { Box::from_raw(arg as *mut Slot) };
+    let result = if s { Ok(()) } else { Err(io_error(bio)) };
+    unsafe { spdk_bdev_free_io(bio) };
+    let mut completions = completions.borrow_mut();
+    completions.done.push_back((io, result));
+    if let Some(waker) = completions.waker.take() {
+        waker.wake();
+    }
+}
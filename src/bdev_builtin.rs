@@ -0,0 +1,425 @@
+//! Create and delete the bdevs of the modules built into SPDK at runtime
+
+use crate::bdev::{do_async, BDev, DifType, IoType};
+use crate::complete::LocalComplete;
+use crate::{Result, SpdkError};
+use log::*;
+use spdk_sys::*;
+use std::{
+    ffi::{c_void, CString},
+    os::raw::c_int,
+};
+
+/// Options to create a malloc bdev, a ramdisk.
+#[derive(Debug, Clone)]
+pub struct MallocOpts {
+    name: String,
+    num_blocks: u64,
+    block_size: u32,
+    optimal_io_boundary: u32,
+    md_size: u32,
+    md_interleave: bool,
+    dif_type: DifType,
+    dif_is_head_of_md: bool,
+}
+
+impl MallocOpts {
+    pub fn new(name: &str, num_blocks: u64, block_size: u32) -> Self {
+        MallocOpts {
+            name: name.into(),
+            num_blocks,
+            block_size,
+            optimal_io_boundary: 0,
+            md_size: 0,
+            md_interleave: false,
+            dif_type: DifType::Disable,
+            dif_is_head_of_md: false,
+        }
+    }
+
+    /// Split I/O on boundaries of `optimal_io_boundary` blocks.
+    pub fn optimal_io_boundary(mut self, optimal_io_boundary: u32) -> Self {
+        self.optimal_io_boundary = optimal_io_boundary;
+        self
+    }
+
+    /// Add `md_size` bytes of metadata to each block, interleaved or separate.
+    pub fn metadata(mut self, md_size: u32, md_interleave: bool) -> Self {
+        self.md_size = md_size;
+        self.md_interleave = md_interleave;
+        self
+    }
+
+    /// Protect the blocks with DIF in the metadata.
+    pub fn dif(mut self, dif_type: DifType, dif_is_head_of_md: bool) -> Self {
+        self.dif_type = dif_type;
+        self.dif_is_head_of_md = dif_is_head_of_md;
+        self
+    }
+
+    pub async fn create(&self) -> Result<BDev> {
+        let name = CString::new(self.name.as_str()).expect("Could not parse to CString");
+        let mut opts: malloc_bdev_opts = unsafe { std::mem::zeroed() };
+        opts.name = name.as_ptr() as _;
+        opts.num_blocks = self.num_blocks;
+        opts.block_size = self.block_size;
+        opts.optimal_io_boundary = self.optimal_io_boundary;
+        opts.md_size = self.md_size;
+        opts.md_interleave = self.md_interleave;
+        opts.dif_type = self.dif_type.into();
+        opts.dif_is_head_of_md = self.dif_is_head_of_md;
+        let mut ptr = std::ptr::null_mut();
+        let err = unsafe { create_malloc_disk(&mut ptr, &opts) };
+        SpdkError::from_retval(err)?;
+        Ok(BDev { ptr })
+    }
+}
+
+/// Delete a malloc bdev.
+pub async fn delete_malloc(name: &str) -> Result<()> {
+    let name = CString::new(name).expect("Could not parse to CString");
+    do_async(|arg| unsafe { delete_malloc_disk(name.as_ptr(), Some(callback), arg) }).await
+}
+
+/// Options to create a null bdev, which discards writes and returns zeroes on reads.
+#[derive(Debug, Clone)]
+pub struct NullOpts {
+    name: String,
+    num_blocks: u64,
+    block_size: u32,
+    md_size: u32,
+    md_interleave: bool,
+    dif_type: DifType,
+    dif_is_head_of_md: bool,
+}
+
+impl NullOpts {
+    pub fn new(name: &str, num_blocks: u64, block_size: u32) -> Self {
+        NullOpts {
+            name: name.into(),
+            num_blocks,
+            block_size,
+            md_size: 0,
+            md_interleave: false,
+            dif_type: DifType::Disable,
+            dif_is_head_of_md: false,
+        }
+    }
+
+    /// Add `md_size` bytes of metadata to each block, interleaved or separate.
+    pub fn metadata(mut self, md_size: u32, md_interleave: bool) -> Self {
+        self.md_size = md_size;
+        self.md_interleave = md_interleave;
+        self
+    }
+
+    /// Protect the blocks with DIF in the metadata.
+    pub fn dif(mut self, dif_type: DifType, dif_is_head_of_md: bool) -> Self {
+        self.dif_type = dif_type;
+        self.dif_is_head_of_md = dif_is_head_of_md;
+        self
+    }
+
+    pub async fn create(&self) -> Result<BDev> {
+        let name = CString::new(self.name.as_str()).expect("Could not parse to CString");
+        let mut opts: spdk_null_bdev_opts = unsafe { std::mem::zeroed() };
+        opts.name = name.as_ptr();
+        opts.num_blocks = self.num_blocks;
+        opts.block_size = self.block_size;
+        opts.md_size = self.md_size;
+        opts.md_interleave = self.md_interleave;
+        opts.dif_type = self.dif_type.into();
+        opts.dif_is_head_of_md = self.dif_is_head_of_md;
+        let mut ptr = std::ptr::null_mut();
+        let err = unsafe { bdev_null_create(&mut ptr, &opts) };
+        SpdkError::from_retval(err)?;
+        Ok(BDev { ptr })
+    }
+}
+
+/// Delete a null bdev.
+pub async fn delete_null(name: &str) -> Result<()> {
+    let name = CString::new(name).expect("Could not parse to CString");
+    do_async(|arg| unsafe { bdev_null_delete(name.as_ptr(), Some(callback), arg) }).await
+}
+
+/// Create a bdev on a file or a block device with Linux AIO.
+///
+/// If `block_size` is 0, it is detected from the file.
+pub async fn create_aio(
+    name: &str,
+    filename: &str,
+    block_size: u32,
+    readonly: bool,
+) -> Result<BDev> {
+    let cname = CString::new(name).expect("Could not parse to CString");
+    let filename = CString::new(filename).expect("Could not parse to CString");
+    let err = unsafe { create_aio_bdev(cname.as_ptr(), filename.as_ptr(), block_size, readonly) };
+    SpdkError::from_retval(err)?;
+    get_created(name)
+}
+
+/// Delete an AIO bdev.
+pub async fn delete_aio(name: &str) -> Result<()> {
+    let name = CString::new(name).expect("Could not parse to CString");
+    do_async(|arg| unsafe { bdev_aio_delete(name.as_ptr(), Some(callback), arg) }).await
+}
+
+/// Create a bdev on a file or a block device with io_uring.
+///
+/// If `block_size` is 0, it is detected from the file.
+#[cfg(feature = "uring")]
+pub async fn create_uring(name: &str, filename: &str, block_size: u32) -> Result<BDev> {
+    let name = CString::new(name).expect("Could not parse to CString");
+    let filename = CString::new(filename).expect("Could not parse to CString");
+    let ptr = unsafe { create_uring_bdev(name.as_ptr(), filename.as_ptr(), block_size) };
+    if ptr.is_null() {
+        return Err(SpdkError::from(-(EINVAL as i32)));
+    }
+    Ok(BDev { ptr })
+}
+
+/// Delete an io_uring bdev.
+#[cfg(feature = "uring")]
+pub async fn delete_uring(name: &str) -> Result<()> {
+    let name = CString::new(name).expect("Could not parse to CString");
+    do_async(|arg| unsafe { delete_uring_bdev(name.as_ptr(), Some(callback), arg) }).await
+}
+
+/// Split the base bdev into `split_count` bdevs named `<base>p<n>`.
+///
+/// If `split_size_mb` is 0, the base bdev is split evenly.
+pub async fn create_split(
+    base_name: &str,
+    split_count: u32,
+    split_size_mb: u64,
+) -> Result<Vec<BDev>> {
+    let cname = CString::new(base_name).expect("Could not parse to CString");
+    let err = unsafe { create_vbdev_split(cname.as_ptr(), split_count as _, split_size_mb) };
+    SpdkError::from_retval(err)?;
+    (0..split_count)
+        .map(|i| get_created(&format!("{}p{}", base_name, i)))
+        .collect()
+}
+
+/// Delete all the split bdevs of the base bdev.
+pub async fn delete_split(base_name: &str) -> Result<()> {
+    let cname = CString::new(base_name).expect("Could not parse to CString");
+    let err = unsafe { vbdev_split_destruct(cname.as_ptr()) };
+    SpdkError::from_retval(err)
+}
+
+/// Latencies added by a delay bdev in microseconds.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct DelayLatencies {
+    pub avg_read: u64,
+    pub p99_read: u64,
+    pub avg_write: u64,
+    pub p99_write: u64,
+}
+
+/// Create a bdev adding latencies to the I/O on the base bdev.
+pub async fn create_delay(base_name: &str, name: &str, latencies: DelayLatencies) -> Result<BDev> {
+    let base_cname = CString::new(base_name).expect("Could not parse to CString");
+    let cname = CString::new(name).expect("Could not parse to CString");
+    let err = unsafe {
+        create_delay_disk(
+            base_cname.as_ptr(),
+            cname.as_ptr(),
+            latencies.avg_read,
+            latencies.p99_read,
+            latencies.avg_write,
+            latencies.p99_write,
+        )
+    };
+    SpdkError::from_retval(err)?;
+    get_created(name)
+}
+
+/// Delete a delay bdev.
+pub async fn delete_delay(name: &str) -> Result<()> {
+    let name = CString::new(name).expect("Could not parse to CString");
+    do_async(|arg| unsafe { delete_delay_disk(name.as_ptr(), Some(callback), arg) }).await
+}
+
+/// Create a bdev passing all I/O through to the base bdev.
+pub async fn create_passthru(base_name: &str, name: &str) -> Result<BDev> {
+    let base_cname = CString::new(base_name).expect("Could not parse to CString");
+    let cname = CString::new(name).expect("Could not parse to CString");
+    let err = unsafe { bdev_passthru_create_disk(base_cname.as_ptr(), cname.as_ptr()) };
+    SpdkError::from_retval(err)?;
+    get_created(name)
+}
+
+/// Delete a passthru bdev.
+pub async fn delete_passthru(name: &str) -> Result<()> {
+    let name = CString::new(name).expect("Could not parse to CString");
+    do_async(|arg| unsafe { bdev_passthru_delete_disk(name.as_ptr(), Some(callback), arg) }).await
+}
+
+/// Create an error injection bdev named `EE_<base>` on the base bdev.
+pub async fn create_error(base_name: &str) -> Result<BDev> {
+    let cname = CString::new(base_name).expect("Could not parse to CString");
+    let err = unsafe { bdev_error_create(cname.as_ptr()) };
+    SpdkError::from_retval(err)?;
+    get_created(&format!("EE_{}", base_name))
+}
+
+/// Delete an error injection bdev.
+pub async fn delete_error(name: &str) -> Result<()> {
+    let name = CString::new(name).expect("Could not parse to CString");
+    do_async(|arg| unsafe { bdev_error_delete(name.as_ptr(), Some(callback), arg) }).await
+}
+
+/// Error injected into the I/O of an error bdev.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InjectedError {
+    /// Fail the I/O.
+    Failure,
+    /// Never complete the I/O until the bdev is reset.
+    Pending,
+    /// XOR the byte at `offset` of the data with `value`, then complete successfully.
+    CorruptData { offset: u64, value: u8 },
+    /// Complete with NOMEM, so the bdev layer retries the I/O later.
+    Nomem,
+}
+
+/// Inject `error` into the next `count` I/Os of `io_type` on an error bdev,
+/// or of all I/O types if `io_type` is `None`.
+pub fn inject_error(
+    name: &str,
+    io_type: Option<IoType>,
+    error: InjectedError,
+    count: u32,
+) -> Result<()> {
+    let mut opts: vbdev_error_inject_opts = unsafe { std::mem::zeroed() };
+    opts.io_type = io_type.map_or(u32::MAX, spdk_bdev_io_type::from);
+    opts.error_type = match error {
+        InjectedError::Failure => vbdev_error_type_VBDEV_IO_FAILURE,
+        InjectedError::Pending => vbdev_error_type_VBDEV_IO_PENDING,
+        InjectedError::CorruptData { offset, value } => {
+            opts.corrupt_offset = offset;
+            opts.corrupt_value = value;
+            vbdev_error_type_VBDEV_IO_CORRUPT_DATA
+        }
+        InjectedError::Nomem => vbdev_error_type_VBDEV_IO_NOMEM,
+    };
+    opts.error_num = count;
+    error_inject(name, &opts)
+}
+
+/// Stop injecting errors into the I/O of an error bdev.
+pub fn clear_errors(name: &str) -> Result<()> {
+    // I/O type 0 clears the errors of all types
+    let opts: vbdev_error_inject_opts = unsafe { std::mem::zeroed() };
+    error_inject(name, &opts)
+}
+
+fn error_inject(name: &str, opts: &vbdev_error_inject_opts) -> Result<()> {
+    let name = CString::new(name).expect("Could not parse to CString");
+    let err = unsafe { vbdev_error_inject_error(name.as_ptr() as *mut _, opts) };
+    SpdkError::from_retval(err)
+}
+
+/// RAID level.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum RaidLevel {
+    Raid0,
+    Raid1,
+    Raid5f,
+    Concat,
+}
+
+impl From<RaidLevel> for raid_level {
+    fn from(level: RaidLevel) -> Self {
+        match level {
+            RaidLevel::Raid0 => raid_level_RAID0,
+            RaidLevel::Raid1 => raid_level_RAID1,
+            RaidLevel::Raid5f => raid_level_RAID5F,
+            RaidLevel::Concat => raid_level_CONCAT,
+        }
+    }
+}
+
+/// Options to create a RAID bdev.
+#[derive(Debug, Clone)]
+pub struct RaidOpts {
+    name: String,
+    level: RaidLevel,
+    strip_size_kb: u32,
+    base_bdevs: Vec<String>,
+}
+
+impl RaidOpts {
+    pub fn new(name: &str, level: RaidLevel) -> Self {
+        RaidOpts {
+            name: name.into(),
+            level,
+            strip_size_kb: 0,
+            base_bdevs: vec![],
+        }
+    }
+
+    /// Size of a strip in KiB, required except for RAID1.
+    pub fn strip_size_kb(mut self, strip_size_kb: u32) -> Self {
+        self.strip_size_kb = strip_size_kb;
+        self
+    }
+
+    /// Add a base bdev, it must already exist.
+    pub fn base_bdev(mut self, name: &str) -> Self {
+        self.base_bdevs.push(name.into());
+        self
+    }
+
+    pub async fn create(&self) -> Result<BDev> {
+        let name = CString::new(self.name.as_str()).expect("Could not parse to CString");
+        let mut raid = std::ptr::null_mut();
+        let err = unsafe {
+            raid_bdev_create(
+                name.as_ptr(),
+                self.strip_size_kb,
+                self.base_bdevs.len() as u8,
+                self.level.into(),
+                &mut raid,
+                std::ptr::null(),
+            )
+        };
+        SpdkError::from_retval(err)?;
+        for (slot, base) in self.base_bdevs.iter().enumerate() {
+            let base = CString::new(base.as_str()).expect("Could not parse to CString");
+            let err = unsafe { raid_bdev_add_base_device(raid, base.as_ptr(), slot as u8) };
+            if err != 0 {
+                // report the failure to add, not the one of the cleanup
+                let ret =
+                    do_async::<()>(|arg| unsafe { raid_bdev_delete(raid, Some(callback), arg) })
+                        .await;
+                if let Err(e) = ret {
+                    warn!("failed to delete the incomplete RAID bdev: {}", e);
+                }
+                return Err(SpdkError::from(err));
+            }
+        }
+        get_created(&self.name)
+    }
+}
+
+/// Delete a RAID bdev, the base bdevs are released.
+pub async fn delete_raid(name: &str) -> Result<()> {
+    let cname = CString::new(name).expect("Could not parse to CString");
+    let raid = unsafe { raid_bdev_find_by_name(cname.as_ptr()) };
+    if raid.is_null() {
+        return Err(SpdkError::from(-(ENODEV as i32)));
+    }
+    do_async(|arg| unsafe { raid_bdev_delete(raid, Some(callback), arg) }).await
+}
+
+/// Get a bdev registered by a module which does not return it.
+fn get_created(name: &str) -> Result<BDev> {
+    BDev::get_by_name(name).ok_or_else(|| SpdkError::from(-(ENODEV as i32)))
+}
+
+extern "C" fn callback(arg: *mut c_void, bdeverrno: c_int) {
+    let complete = unsafe { &mut *(arg as *mut LocalComplete<Result<()>>) };
+    complete.complete(SpdkError::from_retval(bdeverrno));
+}
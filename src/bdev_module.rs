@@ -0,0 +1,270 @@
+//! Block devices implemented in Rust
+
+use crate::bdev::{BDev, BdevIo, IoType};
+use crate::complete::LocalComplete;
+use crate::{Result, SpdkError};
+use lazy_static::lazy_static;
+use log::*;
+use spdk_sys::*;
+use std::{
+    collections::HashMap,
+    ffi::{c_void, CString},
+    os::raw::{c_char, c_int},
+    sync::Mutex,
+};
+
+/// A bdev module, each value of the implementing type is a bdev.
+///
+/// Any thread can open the bdev, and the bdev layer calls `io_type_supported` and
+/// `submit_request` on the thread of each I/O channel, so the bdev is shared between
+/// threads. Per-thread state belongs in `Channel`, which is only used on its thread.
+/// The methods may be reentered when an I/O is completed inside `submit_request`.
+pub trait BdevModule: Send + Sync + Sized + 'static {
+    /// Name of the module, shared by all its bdevs.
+    const NAME: &'static str;
+
+    /// Per-thread context of the bdev.
+    type Channel: 'static;
+
+    /// Whether the bdev can handle the I/O type.
+    fn io_type_supported(&self, io_type: IoType) -> bool;
+
+    /// Create the context of the bdev on the current thread.
+    fn create_channel(&self) -> Self::Channel;
+
+    /// Handle an I/O on `channel`.
+    ///
+    /// The buffers of reads are always allocated. The I/O can be completed later,
+    /// e.g. from a future spawned with `event::spawn`.
+    fn submit_request(&self, channel: &Self::Channel, io: BdevIo);
+
+    /// Called when the bdev is unregistered, before it is dropped.
+    fn destruct(&self) {}
+
+    /// Write the information of the bdev shown in `bdev_get_bdevs`.
+    fn dump_info_json(&self, _w: &mut JsonWriter) {}
+}
+
+/// Write a JSON object inside `dump_info_json`.
+pub struct JsonWriter {
+    pub(crate) ptr: *mut spdk_json_write_ctx,
+}
+
+impl JsonWriter {
+    pub fn named_string(&mut self, name: &str, val: &str) {
+        let name = CString::new(name).expect("Could not parse to CString");
+        let val = CString::new(val).expect("Could not parse to CString");
+        unsafe { spdk_json_write_named_string(self.ptr, name.as_ptr(), val.as_ptr()) };
+    }
+
+    pub fn named_u64(&mut self, name: &str, val: u64) {
+        let name = CString::new(name).expect("Could not parse to CString");
+        unsafe { spdk_json_write_named_uint64(self.ptr, name.as_ptr(), val) };
+    }
+
+    pub fn named_i64(&mut self, name: &str, val: i64) {
+        let name = CString::new(name).expect("Could not parse to CString");
+        unsafe { spdk_json_write_named_int64(self.ptr, name.as_ptr(), val) };
+    }
+
+    pub fn named_bool(&mut self, name: &str, val: bool) {
+        let name = CString::new(name).expect("Could not parse to CString");
+        unsafe { spdk_json_write_named_bool(self.ptr, name.as_ptr(), val) };
+    }
+
+    /// Write a nested object with `f`.
+    pub fn named_object(&mut self, name: &str, f: impl FnOnce(&mut JsonWriter)) {
+        let name = CString::new(name).expect("Could not parse to CString");
+        unsafe { spdk_json_write_named_object_begin(self.ptr, name.as_ptr()) };
+        f(self);
+        unsafe { spdk_json_write_object_end(self.ptr) };
+    }
+}
+
+/// Register a bdev implemented by `bdev`.
+///
+/// The bdev is dropped after it is unregistered, either by `unregister` or when the
+/// application stops.
+pub fn register<M: BdevModule>(
+    name: &str,
+    block_size: u32,
+    num_blocks: u64,
+    bdev: M,
+) -> Result<BDev> {
+    let mut ctx = Box::new(BdevCtx {
+        bdev: unsafe { std::mem::zeroed() },
+        fn_table: spdk_bdev_fn_table {
+            destruct: Some(destruct::<M>),
+            submit_request: Some(submit_request::<M>),
+            io_type_supported: Some(io_type_supported::<M>),
+            get_io_channel: Some(get_io_channel),
+            dump_info_json: Some(dump_info_json::<M>),
+            ..unsafe { std::mem::zeroed() }
+        },
+        name: CString::new(name).expect("Could not parse to CString"),
+        product_name: CString::new(M::NAME).expect("Could not parse to CString"),
+        module: bdev,
+    });
+    ctx.bdev.name = ctx.name.as_ptr() as *mut c_char;
+    ctx.bdev.product_name = ctx.product_name.as_ptr() as *mut c_char;
+    ctx.bdev.blocklen = block_size;
+    ctx.bdev.blockcnt = num_blocks;
+    ctx.bdev.module = module::<M>();
+    ctx.bdev.fn_table = &ctx.fn_table;
+    let ptr = Box::into_raw(ctx);
+    unsafe {
+        (*ptr).bdev.ctxt = ptr as _;
+        // the bdev is its own io_device, the channel context holds a `Box<M::Channel>`
+        spdk_io_device_register(
+            ptr as _,
+            Some(create_channel::<M>),
+            Some(destroy_channel::<M>),
+            std::mem::size_of::<*mut M::Channel>() as u32,
+            (*ptr).name.as_ptr(),
+        );
+        let err = spdk_bdev_register(&mut (*ptr).bdev);
+        if err != 0 {
+            spdk_io_device_unregister(ptr as _, Some(free_bdev::<M>));
+            return Err(SpdkError::from(err));
+        }
+        Ok(BDev {
+            ptr: &mut (*ptr).bdev,
+        })
+    }
+}
+
+/// Unregister a bdev of the module `M` by name.
+pub async fn unregister<M: BdevModule>(name: &str) -> Result<()> {
+    let cname = CString::new(name).expect("Could not parse to CString");
+    let complete = LocalComplete::<Result<()>>::new();
+    futures_lite::pin!(complete);
+    let err = unsafe {
+        spdk_bdev_unregister_by_name(
+            cname.as_ptr(),
+            module::<M>(),
+            Some(unregister_callback),
+            complete.as_arg(),
+        )
+    };
+    SpdkError::from_retval(err)?;
+    complete.await
+}
+
+extern "C" fn unregister_callback(arg: *mut c_void, err: c_int) {
+    let complete = unsafe { &mut *(arg as *mut LocalComplete<Result<()>>) };
+    complete.complete(SpdkError::from_retval(err));
+}
+
+/// The bdev and the context of a bdev implemented in Rust.
+struct BdevCtx<M> {
+    bdev: spdk_bdev,
+    fn_table: spdk_bdev_fn_table,
+    name: CString,
+    product_name: CString,
+    module: M,
+}
+
+/// Get the `spdk_bdev_module` of `M`, adding it to the bdev layer on the first call.
+fn module<M: BdevModule>() -> *mut spdk_bdev_module {
+    module_by_name(M::NAME)
+}
+
+/// Get the `spdk_bdev_module` named `name`, adding it to the bdev layer on the first call.
+pub(crate) fn module_by_name(name: &'static str) -> *mut spdk_bdev_module {
+    extern "C" fn module_init() -> c_int {
+        0
+    }
+
+    lazy_static! {
+        static ref MODULES: Mutex<HashMap<&'static str, usize>> = Mutex::new(HashMap::new());
+    }
+    let mut modules = MODULES.lock().unwrap();
+    *modules.entry(name).or_insert_with(|| {
+        let mut module: spdk_bdev_module = unsafe { std::mem::zeroed() };
+        module.name = CString::new(name)
+            .expect("Could not parse to CString")
+            .into_raw();
+        module.module_init = Some(module_init);
+        let ptr = Box::into_raw(Box::new(module));
+        unsafe { spdk_bdev_module_list_add(ptr) };
+        ptr as usize
+    }) as *mut spdk_bdev_module
+}
+
+extern "C" fn destruct<M: BdevModule>(ctx: *mut c_void) -> c_int {
+    let bdev_ctx = unsafe { &*(ctx as *const BdevCtx<M>) };
+    bdev_ctx.module.destruct();
+    // all channels have been released, free the bdev once the io_device is gone
+    unsafe { spdk_io_device_unregister(ctx, Some(free_bdev::<M>)) };
+    0
+}
+
+extern "C" fn free_bdev<M: BdevModule>(io_device: *mut c_void) {
+    drop(unsafe { Box::from_raw(io_device as *mut BdevCtx<M>) });
+}
+
+extern "C" fn submit_request<M: BdevModule>(ch: *mut spdk_io_channel, bio: *mut spdk_bdev_io) {
+    let io = BdevIo { ptr: bio };
+    // reads may be submitted without a buffer
+    let iovs = unsafe { (*bio).u.bdev.iovs };
+    if io.io_type() == Some(IoType::Read)
+        && (iovs.is_null() || unsafe { (*iovs).iov_base.is_null() })
+    {
+        let len = io.num_blocks() * io.bdev().get_block_size() as u64;
+        std::mem::forget(io);
+        unsafe { spdk_bdev_io_get_buf(bio, Some(get_buf_callback::<M>), len) };
+        return;
+    }
+    dispatch::<M>(ch, io);
+}
+
+extern "C" fn get_buf_callback<M: BdevModule>(
+    ch: *mut spdk_io_channel,
+    bio: *mut spdk_bdev_io,
+    success: bool,
+) {
+    let io = BdevIo { ptr: bio };
+    if !success {
+        warn!("failed to get a buffer for bdev I/O");
+        return io.complete(Err(SpdkError::from(-(ENOMEM as i32))));
+    }
+    dispatch::<M>(ch, io);
+}
+
+fn dispatch<M: BdevModule>(ch: *mut spdk_io_channel, io: BdevIo) {
+    let bdev_ctx = unsafe { &*((*io.bdev().ptr).ctxt as *const BdevCtx<M>) };
+    let channel = unsafe { &**(spdk_io_channel_get_ctx(ch) as *const *mut M::Channel) };
+    bdev_ctx.module.submit_request(channel, io);
+}
+
+extern "C" fn io_type_supported<M: BdevModule>(
+    ctx: *mut c_void,
+    io_type: spdk_bdev_io_type,
+) -> bool {
+    let bdev_ctx = unsafe { &*(ctx as *const BdevCtx<M>) };
+    IoType::from_raw(io_type).is_some_and(|io_type| bdev_ctx.module.io_type_supported(io_type))
+}
+
+extern "C" fn get_io_channel(ctx: *mut c_void) -> *mut spdk_io_channel {
+    unsafe { spdk_get_io_channel(ctx) }
+}
+
+extern "C" fn dump_info_json<M: BdevModule>(
+    ctx: *mut c_void,
+    w: *mut spdk_json_write_ctx,
+) -> c_int {
+    let bdev_ctx = unsafe { &*(ctx as *const BdevCtx<M>) };
+    JsonWriter { ptr: w }.named_object(M::NAME, |w| bdev_ctx.module.dump_info_json(w));
+    0
+}
+
+extern "C" fn create_channel<M: BdevModule>(io_device: *mut c_void, ctx_buf: *mut c_void) -> c_int {
+    let bdev_ctx = unsafe { &*(io_device as *const BdevCtx<M>) };
+    let channel = Box::new(bdev_ctx.module.create_channel());
+    unsafe { *(ctx_buf as *mut *mut M::Channel) = Box::into_raw(channel) };
+    0
+}
+
+extern "C" fn destroy_channel<M: BdevModule>(_io_device: *mut c_void, ctx_buf: *mut c_void) {
+    drop(unsafe { Box::from_raw(*(ctx_buf as *mut *mut M::Channel)) });
+}
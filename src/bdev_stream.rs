@@ -0,0 +1,307 @@
+//! Byte-addressed async I/O over a bdev
+
+use crate::bdev::{BdevDesc, BdevIoChannel};
+use crate::env::DmaBuf;
+use crate::Result;
+use futures_lite::ready;
+use std::{
+    future::Future,
+    io::{self, SeekFrom},
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+/// Size of the bounce buffer of a `BdevStream`, rounded up to whole blocks.
+pub const STREAM_BUF_SIZE: usize = 128 * 1024;
+
+/// Blocks held by the bounce buffer.
+struct Cached {
+    buf: DmaBuf,
+    /// First block in the buffer
+    start_block: u64,
+    /// Number of valid blocks, zero if the buffer holds no data
+    num_blocks: u64,
+}
+
+impl Cached {
+    fn contains(&self, start_block: u64, end_block: u64) -> bool {
+        self.start_block <= start_block && end_block <= self.start_block + self.num_blocks
+    }
+}
+
+type PendingIo<'a> = Pin<Box<dyn Future<Output = Result<Cached>> + 'a>>;
+
+/// A cursor over an opened bdev, implementing `AsyncRead`, `AsyncWrite` and `AsyncSeek`
+/// of both tokio and futures-io.
+///
+/// Data goes through a bounce `DmaBuf`, so offsets and lengths need not be aligned,
+/// and partial blocks are written with read-modify-write. Reads may be served from the
+/// buffer, so the stream should be the only writer of the bdev while it is used.
+///
+/// Like `tokio::fs::File`, a write returns once the data is copied into the buffer, and
+/// its error is returned by the next operation. Flushing waits for the write, and
+/// dropping the stream aborts it.
+pub struct BdevStream<'a> {
+    desc: &'a BdevDesc,
+    channel: &'a BdevIoChannel<'a>,
+    block_size: u64,
+    num_blocks: u64,
+    buf_blocks: u64,
+    buf_align: usize,
+    pos: u64,
+    /// The bounce buffer, `None` while it is owned by `pending` or lost by a failed I/O.
+    cached: Option<Cached>,
+    pending: Option<PendingIo<'a>>,
+}
+
+impl<'a> BdevStream<'a> {
+    /// Create a stream at the start of the bdev, doing I/O on `channel`.
+    pub fn new(desc: &'a BdevDesc, channel: &'a BdevIoChannel<'a>) -> Result<Self> {
+        let bdev = desc.get_bdev()?;
+        let block_size = bdev.get_block_size() as u64;
+        Ok(BdevStream {
+            desc,
+            channel,
+            block_size,
+            num_blocks: bdev.num_blocks(),
+            buf_blocks: (STREAM_BUF_SIZE as u64).div_ceil(block_size),
+            buf_align: bdev.get_buf_align(),
+            pos: 0,
+            cached: None,
+            pending: None,
+        })
+    }
+
+    /// Get the current position in bytes.
+    pub fn position(&self) -> u64 {
+        self.pos
+    }
+
+    /// Get the size of the bdev in bytes.
+    pub fn size(&self) -> u64 {
+        self.num_blocks * self.block_size
+    }
+
+    /// Take the bounce buffer, allocating it if it is lost.
+    ///
+    /// Return `-ENOMEM`, an `OutOfMemory` I/O error, if it can not be allocated.
+    fn take_cached(&mut self) -> Result<Cached> {
+        if let Some(cached) = self.cached.take() {
+            return Ok(cached);
+        }
+        Ok(Cached {
+            buf: DmaBuf::new((self.buf_blocks * self.block_size) as usize, self.buf_align)?,
+            start_block: 0,
+            num_blocks: 0,
+        })
+    }
+
+    /// Wait for the I/O in flight, and get the bounce buffer back.
+    fn poll_pending(&mut self, cx: &mut Context<'_>) -> Poll<Result<()>> {
+        if let Some(pending) = &mut self.pending {
+            let ret = ready!(pending.as_mut().poll(cx));
+            self.pending = None;
+            self.cached = Some(ret?);
+        }
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_read_inner(&mut self, cx: &mut Context<'_>, buf: &mut [u8]) -> Poll<Result<usize>> {
+        ready!(self.poll_pending(cx))?;
+        if buf.is_empty() || self.pos >= self.size() {
+            return Poll::Ready(Ok(0));
+        }
+        let block = self.pos / self.block_size;
+        if !self
+            .cached
+            .as_ref()
+            .is_some_and(|cached| cached.contains(block, block + 1))
+        {
+            let mut cached = self.take_cached()?;
+            let num_blocks = self.buf_blocks.min(self.num_blocks - block);
+            let len = (num_blocks * self.block_size) as usize;
+            let (desc, channel) = (self.desc, self.channel);
+            // the buffer is owned by the I/O, so the stream can be dropped while it is pending
+            self.pending = Some(Box::pin(async move {
+                cached.buf = desc
+                    .submit_owned_blocks(channel, false, block, cached.buf, 0..len)
+                    .await?;
+                cached.start_block = block;
+                cached.num_blocks = num_blocks;
+                Ok(cached)
+            }));
+            ready!(self.poll_pending(cx))?;
+        }
+        let cached = self.cached.as_ref().unwrap();
+        let start = (self.pos - cached.start_block * self.block_size) as usize;
+        let end = (cached.num_blocks * self.block_size) as usize;
+        let n = buf.len().min(end - start);
+        buf[..n].copy_from_slice(&cached.buf.as_ref()[start..start + n]);
+        self.pos += n as u64;
+        Poll::Ready(Ok(n))
+    }
+
+    fn poll_write_inner(&mut self, cx: &mut Context<'_>, data: &[u8]) -> Poll<Result<usize>> {
+        ready!(self.poll_pending(cx))?;
+        if data.is_empty() || self.pos >= self.size() {
+            return Poll::Ready(Ok(0));
+        }
+        let bs = self.block_size;
+        let start_block = self.pos / bs;
+        let end = (self.pos + data.len() as u64)
+            .min((start_block + self.buf_blocks) * bs)
+            .min(self.size());
+        let end_block = end.div_ceil(bs);
+        let n = (end - self.pos) as usize;
+
+        let mut cached = self.take_cached()?;
+        let cached_blocks = cached.contains(start_block, end_block);
+        if !cached_blocks {
+            cached.start_block = start_block;
+            cached.num_blocks = 0;
+        }
+        let range = ((start_block - cached.start_block) * bs) as usize
+            ..((end_block - cached.start_block) * bs) as usize;
+        let offset = (self.pos - cached.start_block * bs) as usize;
+        // partial blocks must be read first, unless they are in the buffer
+        let aligned = self.pos.is_multiple_of(bs) && end.is_multiple_of(bs);
+        let rmw = !cached_blocks && !aligned;
+        let data = if rmw {
+            Some(data[..n].to_vec())
+        } else {
+            cached.buf.as_mut()[offset..offset + n].copy_from_slice(&data[..n]);
+            None
+        };
+        let (desc, channel) = (self.desc, self.channel);
+        let mut pending: PendingIo<'a> = Box::pin(async move {
+            if let Some(data) = data {
+                cached.buf = desc
+                    .submit_owned_blocks(channel, false, start_block, cached.buf, range.clone())
+                    .await?;
+                cached.buf.as_mut()[offset..offset + data.len()].copy_from_slice(&data);
+            }
+            cached.buf = desc
+                .submit_owned_blocks(channel, true, start_block, cached.buf, range)
+                .await?;
+            if !cached_blocks {
+                cached.num_blocks = end_block - start_block;
+            }
+            Ok(cached)
+        });
+        // submit the write now, it is completed by later operations
+        match pending.as_mut().poll(cx) {
+            Poll::Ready(ret) => self.cached = Some(ret?),
+            Poll::Pending => self.pending = Some(pending),
+        }
+        self.pos += n as u64;
+        Poll::Ready(Ok(n))
+    }
+
+    /// Get the position after seeking to `pos`.
+    fn seek_position(&self, pos: SeekFrom) -> io::Result<u64> {
+        let (base, offset) = match pos {
+            SeekFrom::Start(offset) => return Ok(offset),
+            SeekFrom::End(offset) => (self.size(), offset),
+            SeekFrom::Current(offset) => (self.pos, offset),
+        };
+        base.checked_add_signed(offset).ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "invalid seek to a negative or overflowing position",
+            )
+        })
+    }
+}
+
+impl tokio::io::AsyncRead for BdevStream<'_> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut tokio::io::ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let n = ready!(self
+            .get_mut()
+            .poll_read_inner(cx, buf.initialize_unfilled()))?;
+        buf.advance(n);
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl tokio::io::AsyncWrite for BdevStream<'_> {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        self.get_mut()
+            .poll_write_inner(cx, buf)
+            .map_err(io::Error::from)
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        self.get_mut().poll_pending(cx).map_err(io::Error::from)
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        self.get_mut().poll_pending(cx).map_err(io::Error::from)
+    }
+}
+
+impl tokio::io::AsyncSeek for BdevStream<'_> {
+    fn start_seek(self: Pin<&mut Self>, pos: SeekFrom) -> io::Result<()> {
+        let this = self.get_mut();
+        this.pos = this.seek_position(pos)?;
+        Ok(())
+    }
+
+    fn poll_complete(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<u64>> {
+        let this = self.get_mut();
+        ready!(this.poll_pending(cx))?;
+        Poll::Ready(Ok(this.pos))
+    }
+}
+
+impl futures_lite::AsyncRead for BdevStream<'_> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        self.get_mut()
+            .poll_read_inner(cx, buf)
+            .map_err(io::Error::from)
+    }
+}
+
+impl futures_lite::AsyncWrite for BdevStream<'_> {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        self.get_mut()
+            .poll_write_inner(cx, buf)
+            .map_err(io::Error::from)
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        self.get_mut().poll_pending(cx).map_err(io::Error::from)
+    }
+
+    fn poll_close(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        self.get_mut().poll_pending(cx).map_err(io::Error::from)
+    }
+}
+
+impl futures_lite::AsyncSeek for BdevStream<'_> {
+    fn poll_seek(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        pos: SeekFrom,
+    ) -> Poll<io::Result<u64>> {
+        let this = self.get_mut();
+        ready!(this.poll_pending(cx))?;
+        this.pos = this.seek_position(pos)?;
+        Poll::Ready(Ok(this.pos))
+    }
+}
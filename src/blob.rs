@@ -6,6 +6,7 @@ use serde::{Deserialize, Serialize};
 use spdk_sys::*;
 use std::ffi::c_void;
 use std::fmt;
+use std::marker::PhantomData;
 use std::os::raw::c_int;
 use std::sync::{Arc, Mutex};
 use tokio::sync::Notify;
@@ -53,13 +54,16 @@ impl Blobstore {
     }
 
     /// Allocate an I/O channel for the given blobstore.
-    pub fn alloc_io_channel(&self) -> Result<IoChannel> {
+    pub fn alloc_io_channel(&self) -> Result<IoChannel<'_>> {
         let ptr = unsafe { spdk_bs_alloc_io_channel(self.ptr) };
         if ptr.is_null() {
             // FIXME: proper error
             return Err(SpdkError::from(-1));
         }
-        Ok(IoChannel { ptr })
+        Ok(IoChannel {
+            ptr,
+            _blobstore: PhantomData,
+        })
     }
 
     /// Initialize a blobstore on the given device.
@@ -107,8 +111,8 @@ impl Blobstore {
     /// Unload the blobstore.
     ///
     /// It will flush all volatile data to disk.
-    /// WARN: all io_channels must be dropped before unload!
-    pub async fn unload(&self) -> Result<()> {
+    /// The io_channels borrow the blobstore, so they must be dropped before unload.
+    pub async fn unload(self) -> Result<()> {
         do_async(|arg| unsafe {
             spdk_bs_unload(self.ptr, Some(callback), arg);
         })
@@ -116,7 +120,7 @@ impl Blobstore {
         Ok(())
     }
 
-    pub fn unload_sync(&self, cb_arg: *mut c_void) -> Result<()> {
+    pub fn unload_sync(self, cb_arg: *mut c_void) -> Result<()> {
         if self.ptr.is_null() {
             error!("blobstore ptr is null");
         }
@@ -209,15 +213,14 @@ impl fmt::Display for BlobId {
     }
 }
 
+/// I/O channel of a blobstore.
 #[derive(Debug)]
-pub struct IoChannel {
+pub struct IoChannel<'a> {
     pub ptr: *mut spdk_io_channel,
+    _blobstore: PhantomData<&'a Blobstore>,
 }
 
-unsafe impl Send for IoChannel {}
-unsafe impl Sync for IoChannel {}
-
-impl Drop for IoChannel {
+impl Drop for IoChannel<'_> {
     fn drop(&mut self) {
         unsafe { spdk_bs_free_io_channel(self.ptr) };
     }
@@ -265,7 +268,12 @@ impl Blob {
     }
 
     /// Read data from a blob.
-    pub async fn read(&self, io_channel: &IoChannel, offset: u64, buf: &mut [u8]) -> Result<()> {
+    pub async fn read(
+        &self,
+        io_channel: &IoChannel<'_>,
+        offset: u64,
+        buf: &mut [u8],
+    ) -> Result<()> {
         assert_eq!(buf.len() as u64 % self.io_unit_size, 0);
         let units = buf.len() as u64 / self.io_unit_size;
         do_async(|arg| unsafe {
@@ -285,7 +293,7 @@ impl Blob {
     /// Read data from a blob, sync API
     pub fn read_sync(
         &self,
-        io_channel: &IoChannel,
+        io_channel: &IoChannel<'_>,
         offset: u64,
         buf: &mut [u8],
         cb_arg: *mut c_void,
@@ -307,7 +315,7 @@ impl Blob {
     }
 
     /// Write data to a blob.
-    pub async fn write(&self, io_channel: &IoChannel, offset: u64, buf: &[u8]) -> Result<()> {
+    pub async fn write(&self, io_channel: &IoChannel<'_>, offset: u64, buf: &[u8]) -> Result<()> {
         assert_eq!(buf.len() as u64 % self.io_unit_size, 0);
         let units = buf.len() as u64 / self.io_unit_size;
         do_async(|arg| unsafe {
@@ -327,7 +335,7 @@ impl Blob {
     /// Write data to a blob, sync API
     pub fn write_sync(
         &self,
-        io_channel: &IoChannel,
+        io_channel: &IoChannel<'_>,
         offset: u64,
         buf: &[u8],
         cb_arg: *mut c_void,
@@ -349,7 +357,12 @@ impl Blob {
     }
 
     /// Write zeros into area of a blob.
-    pub async fn write_zero(&self, io_channel: &IoChannel, offset: u64, len: u64) -> Result<()> {
+    pub async fn write_zero(
+        &self,
+        io_channel: &IoChannel<'_>,
+        offset: u64,
+        len: u64,
+    ) -> Result<()> {
         assert_eq!(len % self.io_unit_size, 0);
         let units = len / self.io_unit_size;
         do_async(|arg| unsafe {
@@ -361,7 +374,7 @@ impl Blob {
     /// Write zeros to a blob, sync API
     pub fn write_zero_sync(
         &self,
-        io_channel: &IoChannel,
+        io_channel: &IoChannel<'_>,
         offset: u64,
         len: u64,
         cb_arg: *mut c_void,
@@ -1,9 +1,11 @@
+use crate::bdev::{event_callback, BdevEventStream, EventQueue};
 use crate::{Result, SpdkError};
-use log::*;
 use spdk_sys::*;
 use std::{
+    cell::RefCell,
     ffi::{c_void, CString},
     mem::MaybeUninit,
+    rc::Rc,
 };
 
 /// SPDK blob store block device.
@@ -13,6 +15,7 @@ use std::{
 #[derive(Debug)]
 pub struct BlobStoreBDev {
     pub(crate) ptr: *mut spdk_bs_dev,
+    events: Rc<RefCell<EventQueue>>,
 }
 
 impl BlobStoreBDev {
@@ -20,27 +23,32 @@ impl BlobStoreBDev {
     pub fn create(name: &str) -> Result<Self> {
         let cname = CString::new(name).expect("Couldn't create a string");
         let mut ptr = MaybeUninit::uninit();
-        extern "C" fn callback(
-            ty: spdk_bdev_event_type,
-            bdev: *mut spdk_bdev,
-            event_ctx: *mut c_void,
-        ) {
-            warn!(
-                "bdev callback: type={:?}, bdev={:?}, ctx={:?}",
-                ty, bdev, event_ctx
-            );
-        }
+        let events = Rc::new(RefCell::new(EventQueue::default()));
+        // The bs_dev is destroyed by the blobstore, which we can not observe,
+        // so the callback owns a reference to the queue that is never released.
+        let event_ctx = Rc::into_raw(events.clone()) as *mut c_void;
         let err = unsafe {
             spdk_bdev_create_bs_dev_ext(
                 cname.as_ptr(),
-                Some(callback),
-                std::ptr::null_mut(),
+                Some(event_callback),
+                event_ctx,
                 ptr.as_mut_ptr(),
             )
         };
+        if err != 0 {
+            drop(unsafe { Rc::from_raw(event_ctx as *const RefCell<EventQueue>) });
+        }
         SpdkError::from_retval(err)?;
         Ok(BlobStoreBDev {
             ptr: unsafe { ptr.assume_init() },
+            events,
         })
     }
+
+    /// Get the stream of events on the underlying bdev.
+    ///
+    /// Return `None` while another stream of the device is alive.
+    pub fn events(&self) -> Option<BdevEventStream> {
+        BdevEventStream::take(&self.events)
+    }
 }
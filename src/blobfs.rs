@@ -2,9 +2,9 @@
 //!
 //! According to SPDK doc, only synchronous API is tested (except `init`, `load`, `unload`)
 
+use std::marker::PhantomData;
 use std::mem::MaybeUninit;
 
-use crate::blob::IoChannel;
 use crate::event::SpdkEvent;
 use crate::{blob_bdev::BlobStoreBDev, complete::LocalComplete, error::*};
 use log::*;
@@ -25,7 +25,8 @@ impl Default for SpdkFileStat {
     }
 }
 
-#[derive(Debug, Clone, Copy)]
+/// A loaded filesystem, not `Copy` so that unloading it ends the borrows of its channels.
+#[derive(Debug)]
 pub struct SpdkFilesystem {
     pub ptr: *mut spdk_filesystem,
 }
@@ -159,7 +160,7 @@ impl SpdkFile {
 
     pub async fn awrite(
         &self,
-        channel: &IoChannel,
+        channel: &FsIoChannel<'_>,
         data: &[u8],
         offset: u64,
         len: u64,
@@ -181,7 +182,7 @@ impl SpdkFile {
 
     pub async fn aread(
         &self,
-        channel: &IoChannel,
+        channel: &FsIoChannel<'_>,
         data: &mut [u8],
         offset: u64,
         len: u64,
@@ -201,7 +202,7 @@ impl SpdkFile {
         Ok(())
     }
 
-    pub async fn async_sync(&self, channel: &IoChannel) -> Result<()> {
+    pub async fn async_sync(&self, channel: &FsIoChannel<'_>) -> Result<()> {
         do_async(|arg| unsafe {
             spdk_file_sync_async(self.ptr, channel.ptr, Some(callback), arg);
         })
@@ -246,7 +247,9 @@ impl SpdkFilesystem {
     }
 
     /// unload blobfs
-    pub async fn unload(&self) -> Result<()> {
+    ///
+    /// The I/O channels borrow the filesystem, so they must be dropped before unload.
+    pub async fn unload(self) -> Result<()> {
         do_async(|arg| unsafe {
             spdk_fs_unload(self.ptr, Some(callback), arg);
         })
@@ -303,7 +306,7 @@ impl SpdkFilesystem {
 
 /// Sync API
 impl SpdkFilesystem {
-    pub fn unload_sync(&self) -> Result<()> {
+    pub fn unload_sync(self) -> Result<()> {
         unsafe {
             spdk_fs_unload(self.ptr, Some(unload_callback), std::ptr::null_mut());
         }
@@ -318,12 +321,15 @@ impl SpdkFilesystem {
     }
 
     /// Allocate an I/O channel for async operations
-    pub fn alloc_io_channel(&self) -> Result<IoChannel> {
+    pub fn alloc_io_channel(&self) -> Result<FsIoChannel<'_>> {
         let ptr = unsafe { spdk_fs_alloc_io_channel(self.ptr) };
         if ptr.is_null() {
             return Err(SpdkError::from(-1));
         }
-        Ok(IoChannel { ptr })
+        Ok(FsIoChannel {
+            ptr,
+            _fs: PhantomData,
+        })
     }
 
     /// Initialize from raw pointer
@@ -331,11 +337,9 @@ impl SpdkFilesystem {
         Self { ptr: p }
     }
 
-    /// Free I/O channel from blobfs
-    pub fn free_io_channel(&self, channel: IoChannel) -> Result<()> {
-        unsafe {
-            spdk_fs_free_io_channel(channel.ptr);
-        }
+    /// Free I/O channel from blobfs, the same as dropping it
+    pub fn free_io_channel(&self, channel: FsIoChannel<'_>) -> Result<()> {
+        drop(channel);
         Ok(())
     }
 
@@ -362,8 +366,7 @@ impl SpdkFilesystem {
     /// Create file
     pub fn create(&self, ctx: &SpdkFsThreadCtx, name: &str) -> Result<()> {
         let cname = CString::new(name).expect("Failt to parse name");
-        let fs = self.clone();
-        let ret = unsafe { spdk_fs_create_file(fs.ptr, ctx.ptr, cname.as_ptr()) };
+        let ret = unsafe { spdk_fs_create_file(self.ptr, ctx.ptr, cname.as_ptr()) };
         if ret != 0 {
             return Err(SpdkError::from(-1));
         }
@@ -436,6 +439,21 @@ impl Drop for SpdkFsThreadCtx {
     }
 }
 
+/// I/O channel of a filesystem, freed on drop.
+#[derive(Debug)]
+pub struct FsIoChannel<'a> {
+    pub ptr: *mut spdk_io_channel,
+    _fs: PhantomData<&'a SpdkFilesystem>,
+}
+
+impl Drop for FsIoChannel<'_> {
+    fn drop(&mut self) {
+        unsafe {
+            spdk_fs_free_io_channel(self.ptr);
+        }
+    }
+}
+
 #[derive(Debug)]
 pub struct SpdkBlobfsOpts(spdk_blobfs_opts);
 
@@ -30,6 +30,11 @@ impl<T> LocalComplete<T> {
         }
     }
 
+    /// Whether a result is waiting to be polled.
+    pub fn is_complete(&self) -> bool {
+        self.value.is_some()
+    }
+
     pub fn as_arg(&mut self) -> *mut c_void {
         self as *mut Self as _
     }
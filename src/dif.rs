@@ -0,0 +1,279 @@
+//! Data integrity field (DIF/DIX) protection information
+
+use crate::bdev::{BDev, DifCheck, DifType};
+use crate::{Result, SpdkError};
+use spdk_sys::*;
+use std::{convert::TryFrom, mem::MaybeUninit};
+
+/// Options to create a `DifContext`.
+#[derive(Debug, Clone)]
+pub struct DifOpts {
+    block_size: u32,
+    md_size: u32,
+    md_interleave: bool,
+    dif_head_of_md: bool,
+    dif_type: DifType,
+    dif_flags: u32,
+    init_ref_tag: u32,
+    apptag_mask: u16,
+    app_tag: u16,
+    data_offset: u32,
+    guard_seed: u16,
+}
+
+impl DifOpts {
+    /// `block_size` includes the metadata if it is interleaved with the data.
+    pub fn new(block_size: u32, md_size: u32, dif_type: DifType) -> Self {
+        DifOpts {
+            block_size,
+            md_size,
+            md_interleave: true,
+            dif_head_of_md: false,
+            dif_type,
+            dif_flags: 0,
+            init_ref_tag: 0,
+            apptag_mask: 0,
+            app_tag: 0,
+            data_offset: 0,
+            guard_seed: 0,
+        }
+    }
+
+    /// Take the format and the enabled checks from the block device.
+    pub fn from_bdev(bdev: &BDev) -> Self {
+        DifOpts::new(bdev.get_block_size(), bdev.md_size(), bdev.dif_type())
+            .md_interleave(bdev.is_md_interleaved())
+            .dif_head_of_md(bdev.is_dif_head_of_md())
+            .check(DifCheck::Guard, bdev.is_dif_check_enabled(DifCheck::Guard))
+            .check(
+                DifCheck::AppTag,
+                bdev.is_dif_check_enabled(DifCheck::AppTag),
+            )
+            .check(
+                DifCheck::RefTag,
+                bdev.is_dif_check_enabled(DifCheck::RefTag),
+            )
+    }
+
+    /// Whether the metadata is interleaved with the data, otherwise separate (DIX).
+    pub fn md_interleave(mut self, md_interleave: bool) -> Self {
+        self.md_interleave = md_interleave;
+        self
+    }
+
+    /// Whether the DIF is stored in the first 8 bytes of the metadata.
+    pub fn dif_head_of_md(mut self, dif_head_of_md: bool) -> Self {
+        self.dif_head_of_md = dif_head_of_md;
+        self
+    }
+
+    /// Enable or disable generating and checking the tag.
+    pub fn check(mut self, check: DifCheck, enable: bool) -> Self {
+        let flag = match check {
+            DifCheck::Guard => SPDK_DIF_FLAGS_GUARD_CHECK,
+            DifCheck::AppTag => SPDK_DIF_FLAGS_APPTAG_CHECK,
+            DifCheck::RefTag => SPDK_DIF_FLAGS_REFTAG_CHECK,
+        };
+        if enable {
+            self.dif_flags |= flag;
+        } else {
+            self.dif_flags &= !flag;
+        }
+        self
+    }
+
+    /// Reference tag of the first block, usually its LBA.
+    pub fn init_ref_tag(mut self, init_ref_tag: u32) -> Self {
+        self.init_ref_tag = init_ref_tag;
+        self
+    }
+
+    /// Application tag and the mask of the bits to check.
+    pub fn app_tag(mut self, app_tag: u16, apptag_mask: u16) -> Self {
+        self.app_tag = app_tag;
+        self.apptag_mask = apptag_mask;
+        self
+    }
+
+    /// Byte offset of the buffers from the start of the I/O.
+    pub fn data_offset(mut self, data_offset: u32) -> Self {
+        self.data_offset = data_offset;
+        self
+    }
+
+    /// Seed of the guard CRC.
+    pub fn guard_seed(mut self, guard_seed: u16) -> Self {
+        self.guard_seed = guard_seed;
+        self
+    }
+
+    pub fn build(&self) -> Result<DifContext> {
+        if self.block_size == 0 {
+            return Err(SpdkError::InvalidIo("block size is zero".into()));
+        }
+        let mut ctx = MaybeUninit::uninit();
+        let mut ext_opts: spdk_dif_ctx_init_ext_opts = unsafe { std::mem::zeroed() };
+        ext_opts.size = std::mem::size_of::<spdk_dif_ctx_init_ext_opts>() as _;
+        ext_opts.dif_pi_format = spdk_dif_pi_format_SPDK_DIF_PI_FORMAT_16;
+        let err = unsafe {
+            spdk_dif_ctx_init(
+                ctx.as_mut_ptr(),
+                self.block_size,
+                self.md_size,
+                self.md_interleave,
+                self.dif_head_of_md,
+                self.dif_type.into(),
+                self.dif_flags,
+                self.init_ref_tag as _,
+                self.apptag_mask,
+                self.app_tag,
+                self.data_offset,
+                self.guard_seed as _,
+                &mut ext_opts,
+            )
+        };
+        SpdkError::from_retval(err)?;
+        Ok(DifContext {
+            ctx: unsafe { ctx.assume_init() },
+            block_size: self.block_size as usize,
+            md_size: self.md_size as usize,
+        })
+    }
+}
+
+/// Context to generate and verify protection information.
+///
+/// The buffers must hold whole blocks and a separate metadata buffer the metadata
+/// of each of them, otherwise the methods return `InvalidIo`.
+#[derive(Debug)]
+pub struct DifContext {
+    ctx: spdk_dif_ctx,
+    block_size: usize,
+    md_size: usize,
+}
+
+impl DifContext {
+    /// Get the number of blocks in a buffer of `len` bytes, which must be whole blocks.
+    fn num_blocks(&self, len: usize) -> Result<u32> {
+        if !len.is_multiple_of(self.block_size) {
+            return Err(SpdkError::InvalidIo(format!(
+                "buffer length {} is not a multiple of the block size {}",
+                len, self.block_size
+            )));
+        }
+        u32::try_from(len / self.block_size)
+            .map_err(|_| SpdkError::InvalidIo(format!("too many blocks in {} bytes", len)))
+    }
+
+    /// Check that a separate metadata buffer of `len` bytes matches `num_blocks`.
+    fn check_md(&self, len: usize, num_blocks: u32) -> Result<()> {
+        let md_len = num_blocks as usize * self.md_size;
+        if len != md_len {
+            return Err(SpdkError::InvalidIo(format!(
+                "metadata length {} does not match {} blocks of {} bytes",
+                len, num_blocks, self.md_size
+            )));
+        }
+        Ok(())
+    }
+
+    /// Generate the DIF in the metadata interleaved in `buf`.
+    pub fn generate(&self, buf: &mut [u8]) -> Result<()> {
+        let num_blocks = self.num_blocks(buf.len())?;
+        let mut iov = iovec {
+            iov_base: buf.as_mut_ptr() as _,
+            iov_len: buf.len(),
+        };
+        let err = unsafe { spdk_dif_generate(&mut iov, 1, num_blocks, &self.ctx) };
+        SpdkError::from_retval(err)
+    }
+
+    /// Verify the DIF in the metadata interleaved in `buf`.
+    pub fn verify(&self, buf: &[u8]) -> Result<()> {
+        let num_blocks = self.num_blocks(buf.len())?;
+        let mut iov = to_iovec(buf);
+        let mut err_blk = MaybeUninit::zeroed();
+        let err =
+            unsafe { spdk_dif_verify(&mut iov, 1, num_blocks, &self.ctx, err_blk.as_mut_ptr()) };
+        check_verify(err, unsafe { err_blk.assume_init() })
+    }
+
+    /// Generate the DIF of `data` in the separate metadata buffer `md`.
+    pub fn dix_generate(&self, data: &[u8], md: &mut [u8]) -> Result<()> {
+        let num_blocks = self.num_blocks(data.len())?;
+        self.check_md(md.len(), num_blocks)?;
+        let mut iov = to_iovec(data);
+        let mut md_iov = iovec {
+            iov_base: md.as_mut_ptr() as _,
+            iov_len: md.len(),
+        };
+        let err = unsafe { spdk_dix_generate(&mut iov, 1, &mut md_iov, num_blocks, &self.ctx) };
+        SpdkError::from_retval(err)
+    }
+
+    /// Verify the DIF of `data` in the separate metadata buffer `md`.
+    pub fn dix_verify(&self, data: &[u8], md: &[u8]) -> Result<()> {
+        let num_blocks = self.num_blocks(data.len())?;
+        self.check_md(md.len(), num_blocks)?;
+        let mut iov = to_iovec(data);
+        let mut md_iov = to_iovec(md);
+        let mut err_blk = MaybeUninit::zeroed();
+        let err = unsafe {
+            spdk_dix_verify(
+                &mut iov,
+                1,
+                &mut md_iov,
+                num_blocks,
+                &self.ctx,
+                err_blk.as_mut_ptr(),
+            )
+        };
+        check_verify(err, unsafe { err_blk.assume_init() })
+    }
+}
+
+/// Kind of a DIF verification failure.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum DifErrorKind {
+    Guard,
+    AppTag,
+    RefTag,
+    Data,
+}
+
+/// DIF verification failure.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DifError {
+    pub kind: DifErrorKind,
+    pub expected: u64,
+    pub actual: u64,
+    /// Offset of the failed block in blocks
+    pub offset: u32,
+}
+
+fn check_verify(err: i32, err_blk: spdk_dif_error) -> Result<()> {
+    if err == 0 {
+        return Ok(());
+    }
+    let kind = match err_blk.err_type as u32 {
+        SPDK_DIF_GUARD_ERROR => DifErrorKind::Guard,
+        SPDK_DIF_APPTAG_ERROR => DifErrorKind::AppTag,
+        SPDK_DIF_REFTAG_ERROR => DifErrorKind::RefTag,
+        SPDK_DIF_DATA_ERROR => DifErrorKind::Data,
+        _ => return Err(SpdkError::from(err)),
+    };
+    Err(SpdkError::Dif(DifError {
+        kind,
+        expected: err_blk.expected,
+        actual: err_blk.actual,
+        offset: err_blk.err_offset,
+    }))
+}
+
+/// Describe a read-only buffer as an iovec, SPDK does not write through it.
+fn to_iovec(buf: &[u8]) -> iovec {
+    iovec {
+        iov_base: buf.as_ptr() as _,
+        iov_len: buf.len(),
+    }
+}
@@ -1,3 +1,4 @@
+use crate::{Result, SpdkError};
 use spdk_sys::*;
 
 #[derive(Debug)]
@@ -9,18 +10,27 @@ pub struct DmaBuf {
 unsafe impl Send for DmaBuf {}
 
 impl DmaBuf {
-    /// Allocate a pinned memory buffer with the given size and alignment.
-    pub fn alloc(size: usize, align: usize) -> DmaBuf {
+    /// Allocate an uninitialized pinned buffer of `size` bytes aligned to `align`.
+    ///
+    /// Return `-ENOMEM` if the memory can not be allocated.
+    pub fn new(size: usize, align: usize) -> Result<DmaBuf> {
         let ptr = unsafe { spdk_dma_malloc(size as u64, align as u64, std::ptr::null_mut()) };
-        assert!(!ptr.is_null(), "Failed to malloc");
-        DmaBuf {
-            ptr: ptr as _,
-            len: size as usize,
+        if ptr.is_null() {
+            return Err(SpdkError::from(-(ENOMEM as i32)));
         }
+        Ok(DmaBuf {
+            ptr: ptr as _,
+            len: size,
+        })
+    }
+
+    /// Allocate an uninitialized buffer like `new`, panicking if the memory can not
+    /// be allocated.
+    pub fn alloc(size: usize, align: usize) -> DmaBuf {
+        Self::new(size, align).expect("Failed to malloc")
     }
 
-    /// Allocate a pinned memory buffer with the given size and alignment.
-    /// The buffer will be zeroed.
+    /// Allocate a buffer filled with zeroes, panicking if the memory can not be allocated.
     pub fn alloc_zeroed(size: usize, align: usize) -> DmaBuf {
         let ptr = unsafe { spdk_dma_zmalloc(size as u64, align as u64, std::ptr::null_mut()) };
         assert!(!ptr.is_null(), "Failed to malloc");
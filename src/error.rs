@@ -1,24 +1,46 @@
+use crate::bdev::{IoStatus, IoType};
+use crate::dif::DifError;
 use spdk_sys::*;
 use std::ffi::CStr;
 
 #[derive(Debug, thiserror::Error)]
-#[error("spdk error: {msg}")]
-pub struct SpdkError {
-    msg: String,
-    errno: i32,
+pub enum SpdkError {
+    #[error("spdk error: {msg}")]
+    Errno { msg: String, errno: i32 },
+    #[error("spdk error: I/O type {0:?} is not supported by the bdev")]
+    Unsupported(IoType),
+    #[error("spdk error: data miscompare")]
+    Miscompare,
+    #[error("spdk error: bdev I/O failed: {0}")]
+    IoFailed(IoStatus),
+    #[error("spdk error: DIF verification failed: {0:?}")]
+    Dif(DifError),
+    #[error("spdk error: invalid I/O: {0}")]
+    InvalidIo(String),
 }
 
 impl From<i32> for SpdkError {
     fn from(errno: i32) -> Self {
         assert_ne!(errno, 0);
         let cstr = unsafe { spdk_strerror(-errno) };
-        SpdkError {
+        SpdkError::Errno {
             msg: unsafe { CStr::from_ptr(cstr) }.to_str().unwrap().into(),
             errno,
         }
     }
 }
 
+impl From<SpdkError> for std::io::Error {
+    fn from(e: SpdkError) -> Self {
+        use std::io::{Error, ErrorKind};
+        match e {
+            SpdkError::Errno { errno, .. } => Error::from_raw_os_error(-errno),
+            SpdkError::InvalidIo(_) => Error::new(ErrorKind::InvalidInput, e),
+            e => Error::other(e),
+        }
+    }
+}
+
 impl SpdkError {
     pub fn from_retval(errno: i32) -> Result<()> {
         if errno == 0 {
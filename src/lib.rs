@@ -1,12 +1,18 @@
 pub mod bdev;
+pub mod bdev_batch;
+pub mod bdev_builtin;
+pub mod bdev_module;
+pub mod bdev_stream;
 pub mod blob;
 pub mod blob_bdev;
 pub mod blobfs;
 mod complete;
 pub mod cpuset;
+pub mod dif;
 pub mod env;
 mod error;
 pub mod event;
 pub mod thread;
+pub mod vbdev;
 
 pub use crate::error::*;
@@ -0,0 +1,384 @@
+//! Virtual bdevs stacked on a base bdev
+
+use crate::bdev::{io_error, BDev, BdevIo, IoType};
+use crate::bdev_module::{module_by_name, JsonWriter};
+use crate::{Result, SpdkError};
+use lazy_static::lazy_static;
+use log::*;
+use spdk_sys::*;
+use std::{
+    cell::RefCell,
+    collections::HashMap,
+    ffi::{c_void, CString},
+    os::raw::{c_char, c_int},
+    sync::Mutex,
+};
+
+/// A virtual bdev module, each value of the implementing type is a part of a base bdev.
+///
+/// The parts of a base bdev share its claim, and all of them are unregistered
+/// when the base bdev is removed.
+///
+/// A part is shared by its channels, so `submit_request` runs on the threads of all
+/// of them, and the part is dropped on the thread that unregisters it.
+pub trait VBdev: Send + Sync + Sized + 'static {
+    /// Name of the module, shared by all its bdevs.
+    const NAME: &'static str;
+
+    /// Whether the part can handle the I/O type, by default the same as the base bdev.
+    fn io_type_supported(&self, base: &BDev, io_type: IoType) -> bool {
+        base.io_type_supported(io_type)
+    }
+
+    /// Handle an I/O on `channel`, by default forward it to the base bdev.
+    fn submit_request(&self, channel: &PartChannel, io: BdevIo) {
+        channel.forward(io);
+    }
+
+    /// Write the information of the part shown in `bdev_get_bdevs`.
+    fn dump_info_json(&self, _w: &mut JsonWriter) {}
+}
+
+/// Options to create the parts of a base bdev.
+pub struct VBdevOpts<V> {
+    base_name: String,
+    parts: Vec<(String, u64, u64, V)>,
+}
+
+impl<V: VBdev> VBdevOpts<V> {
+    pub fn new(base_name: &str) -> Self {
+        VBdevOpts {
+            base_name: base_name.into(),
+            parts: vec![],
+        }
+    }
+
+    /// Add a part named `name` mapped to `num_blocks` blocks from `offset_blocks` of the base.
+    pub fn part(mut self, name: &str, offset_blocks: u64, num_blocks: u64, vbdev: V) -> Self {
+        self.parts
+            .push((name.into(), offset_blocks, num_blocks, vbdev));
+        self
+    }
+
+    /// Claim the base bdev and register the parts.
+    ///
+    /// If a part fails to register, the parts before it stay registered.
+    pub fn build(self) -> Result<Vec<BDev>> {
+        let module = part_module::<V>();
+        let cname = CString::new(self.base_name).expect("Could not parse to CString");
+        let mut base = std::ptr::null_mut();
+        let err = unsafe {
+            spdk_bdev_part_base_construct_ext(
+                cname.as_ptr(),
+                Some(hotremove),
+                module_by_name(V::NAME),
+                &mut (*module).fn_table,
+                &mut (*module).tailq,
+                None,
+                std::ptr::null_mut(),
+                std::mem::size_of::<spdk_bdev_part_channel>() as u32,
+                None,
+                None,
+                &mut base,
+            )
+        };
+        SpdkError::from_retval(err)?;
+        let mut bdevs = vec![];
+        for (name, offset_blocks, num_blocks, vbdev) in self.parts {
+            match construct_part(base, &name, offset_blocks, num_blocks, vbdev) {
+                Ok(bdev) => bdevs.push(bdev),
+                Err(e) => {
+                    if bdevs.is_empty() {
+                        unsafe { spdk_bdev_part_base_free(base) };
+                    }
+                    return Err(e);
+                }
+            }
+        }
+        if bdevs.is_empty() {
+            unsafe { spdk_bdev_part_base_free(base) };
+        }
+        Ok(bdevs)
+    }
+}
+
+/// Per-thread channel of a part, holding the channel of the base bdev.
+pub struct PartChannel {
+    ptr: *mut spdk_bdev_part_channel,
+}
+
+impl PartChannel {
+    /// Get the base bdev.
+    pub fn base_bdev(&self) -> BDev {
+        BDev {
+            ptr: unsafe { spdk_bdev_part_get_base_bdev((*self.ptr).part) },
+        }
+    }
+
+    /// Get the offset of the part on the base bdev in blocks.
+    pub fn offset_blocks(&self) -> u64 {
+        unsafe { spdk_bdev_part_get_offset_blocks((*self.ptr).part) }
+    }
+
+    /// Submit the I/O to the base bdev at the offset of the part, and complete it
+    /// with the status of the base I/O.
+    pub fn forward(&self, io: BdevIo) {
+        let rc = unsafe { spdk_bdev_part_submit_request(self.ptr, io.ptr) };
+        if rc != 0 {
+            return io.complete(Err(SpdkError::from(rc)));
+        }
+        std::mem::forget(io);
+    }
+
+    /// Submit the I/O to the base bdev like `forward`, then call `f` to complete it.
+    ///
+    /// `f` can inspect or rewrite the data of a read before completing it. A failed read,
+    /// write, unmap, write zeroes or flush gets the status of the base I/O, so completing
+    /// with the error passes it through. Other I/O types only fail with `-EIO`.
+    pub fn forward_with(&self, io: BdevIo, f: impl FnOnce(BdevIo, Result<()>) + 'static) {
+        FORWARDED.with(|forwarded| forwarded.borrow_mut().insert(io.ptr as usize, Box::new(f)));
+        // reads may be submitted without a buffer, the base must read into the one of the part
+        let iovs = unsafe { (*io.ptr).u.bdev.iovs };
+        if io.io_type() == Some(IoType::Read)
+            && (iovs.is_null() || unsafe { (*iovs).iov_base.is_null() })
+        {
+            let len = io.num_blocks() * io.bdev().get_block_size() as u64;
+            let ptr = io.ptr;
+            std::mem::forget(io);
+            unsafe { spdk_bdev_io_get_buf(ptr, Some(forward_get_buf_callback), len) };
+            return;
+        }
+        self.submit_forwarded(io);
+    }
+
+    /// Submit an I/O of `forward_with` to the base bdev.
+    ///
+    /// Block I/O is submitted on the base descriptor directly, since the completion
+    /// callback of `spdk_bdev_part_submit_request_ext` does not get the base I/O.
+    fn submit_forwarded(&self, io: BdevIo) {
+        let ptr = io.ptr;
+        let arg = ptr as *mut c_void;
+        let rc = unsafe {
+            let desc = spdk_bdev_part_base_get_desc(spdk_bdev_part_get_base((*self.ptr).part));
+            let ch = (*self.ptr).base_ch;
+            let offset_blocks = io.offset_blocks() + self.offset_blocks();
+            let num_blocks = io.num_blocks();
+            let cb = Some(forward_callback as _);
+            match io.io_type() {
+                Some(IoType::Read) => spdk_bdev_readv_blocks_with_md(
+                    desc,
+                    ch,
+                    (*ptr).u.bdev.iovs,
+                    (*ptr).u.bdev.iovcnt,
+                    spdk_bdev_io_get_md_buf(ptr),
+                    offset_blocks,
+                    num_blocks,
+                    cb,
+                    arg,
+                ),
+                Some(IoType::Write) => spdk_bdev_writev_blocks_with_md(
+                    desc,
+                    ch,
+                    (*ptr).u.bdev.iovs,
+                    (*ptr).u.bdev.iovcnt,
+                    spdk_bdev_io_get_md_buf(ptr),
+                    offset_blocks,
+                    num_blocks,
+                    cb,
+                    arg,
+                ),
+                Some(IoType::Unmap) => {
+                    spdk_bdev_unmap_blocks(desc, ch, offset_blocks, num_blocks, cb, arg)
+                }
+                Some(IoType::WriteZeroes) => {
+                    spdk_bdev_write_zeroes_blocks(desc, ch, offset_blocks, num_blocks, cb, arg)
+                }
+                Some(IoType::Flush) => {
+                    spdk_bdev_flush_blocks(desc, ch, offset_blocks, num_blocks, cb, arg)
+                }
+                _ => spdk_bdev_part_submit_request_ext(self.ptr, ptr, Some(part_callback)),
+            }
+        };
+        if rc != 0 {
+            FORWARDED.with(|forwarded| forwarded.borrow_mut().remove(&(ptr as usize)));
+            return io.complete(Err(SpdkError::from(rc)));
+        }
+        std::mem::forget(io);
+    }
+}
+
+type ForwardCallback = Box<dyn FnOnce(BdevIo, Result<()>)>;
+
+thread_local! {
+    /// Completion callbacks of the I/O forwarded with `forward_with` on this thread.
+    static FORWARDED: RefCell<HashMap<usize, ForwardCallback>> = RefCell::new(HashMap::new());
+}
+
+/// Call the completion callback of an I/O forwarded with `forward_with`.
+fn complete_forwarded(bio: *mut spdk_bdev_io, result: Result<()>) {
+    let f = FORWARDED
+        .with(|forwarded| forwarded.borrow_mut().remove(&(bio as usize)))
+        .expect("forwarded I/O without callback");
+    f(BdevIo { ptr: bio }, result);
+}
+
+extern "C" fn forward_get_buf_callback(
+    ch: *mut spdk_io_channel,
+    bio: *mut spdk_bdev_io,
+    success: bool,
+) {
+    if !success {
+        warn!("failed to get a buffer for forwarded bdev I/O");
+        FORWARDED.with(|forwarded| forwarded.borrow_mut().remove(&(bio as usize)));
+        return BdevIo { ptr: bio }.complete(Err(SpdkError::from(-(ENOMEM as i32))));
+    }
+    let channel = PartChannel {
+        ptr: unsafe { spdk_io_channel_get_ctx(ch) } as *mut spdk_bdev_part_channel,
+    };
+    channel.submit_forwarded(BdevIo { ptr: bio });
+}
+
+/// Completion of the base I/O of a forwarded I/O, which is passed as `arg`.
+extern "C" fn forward_callback(base_bio: *mut spdk_bdev_io, success: bool, arg: *mut c_void) {
+    let result = if success {
+        Ok(())
+    } else {
+        Err(io_error(base_bio))
+    };
+    unsafe { spdk_bdev_free_io(base_bio) };
+    complete_forwarded(arg as *mut spdk_bdev_io, result);
+}
+
+/// Completion of an I/O forwarded by the part, which only tells whether the base I/O
+/// succeeded.
+extern "C" fn part_callback(bio: *mut spdk_bdev_io, success: bool, _arg: *mut c_void) {
+    let result = if success {
+        Ok(())
+    } else {
+        Err(SpdkError::from(-(EIO as i32)))
+    };
+    complete_forwarded(bio, result);
+}
+
+/// The function table and the list of parts shared by the bdevs of a module.
+struct PartModule {
+    fn_table: spdk_bdev_fn_table,
+    tailq: bdev_part_tailq,
+}
+
+/// A part and its implementation, allocated with `calloc` since SPDK frees the part.
+#[repr(C)]
+struct PartCtx<V> {
+    part: spdk_bdev_part,
+    vbdev: V,
+}
+
+/// Get the `PartModule` of `V`, creating it on the first call.
+fn part_module<V: VBdev>() -> *mut PartModule {
+    lazy_static! {
+        static ref MODULES: Mutex<HashMap<&'static str, usize>> = Mutex::new(HashMap::new());
+    }
+    let mut modules = MODULES.lock().unwrap();
+    *modules.entry(V::NAME).or_insert_with(|| {
+        let ptr = Box::into_raw(Box::new(PartModule {
+            fn_table: spdk_bdev_fn_table {
+                destruct: Some(destruct::<V>),
+                submit_request: Some(submit_request::<V>),
+                io_type_supported: Some(io_type_supported::<V>),
+                get_io_channel: Some(get_io_channel),
+                dump_info_json: Some(dump_info_json::<V>),
+                ..unsafe { std::mem::zeroed() }
+            },
+            tailq: bdev_part_tailq {
+                tqh_first: std::ptr::null_mut(),
+                tqh_last: std::ptr::null_mut(),
+            },
+        }));
+        // TAILQ_INIT
+        unsafe { (*ptr).tailq.tqh_last = &mut (*ptr).tailq.tqh_first };
+        ptr as usize
+    }) as *mut PartModule
+}
+
+fn construct_part<V: VBdev>(
+    base: *mut spdk_bdev_part_base,
+    name: &str,
+    offset_blocks: u64,
+    num_blocks: u64,
+    vbdev: V,
+) -> Result<BDev> {
+    let cname = CString::new(name).expect("Could not parse to CString");
+    let product_name = CString::new(V::NAME).expect("Could not parse to CString");
+    let ctx = unsafe { calloc(1, std::mem::size_of::<PartCtx<V>>() as _) } as *mut PartCtx<V>;
+    if ctx.is_null() {
+        return Err(SpdkError::from(-(ENOMEM as i32)));
+    }
+    unsafe {
+        std::ptr::write(&mut (*ctx).vbdev, vbdev);
+        let err = spdk_bdev_part_construct(
+            &mut (*ctx).part,
+            base,
+            cname.as_ptr() as *mut c_char,
+            offset_blocks,
+            num_blocks,
+            product_name.as_ptr() as *mut c_char,
+        );
+        if err != 0 {
+            std::ptr::drop_in_place(&mut (*ctx).vbdev);
+            free(ctx as _);
+            return Err(SpdkError::from(err));
+        }
+        Ok(BDev {
+            ptr: spdk_bdev_part_get_bdev(&mut (*ctx).part),
+        })
+    }
+}
+
+/// Unregister all parts when the base bdev is removed.
+extern "C" fn hotremove(base: *mut c_void) {
+    let base = base as *mut spdk_bdev_part_base;
+    unsafe { spdk_bdev_part_base_hotremove(base, spdk_bdev_part_base_get_tailq(base)) };
+}
+
+extern "C" fn destruct<V: VBdev>(ctx: *mut c_void) -> c_int {
+    let ctx = ctx as *mut PartCtx<V>;
+    unsafe {
+        std::ptr::drop_in_place(&mut (*ctx).vbdev);
+        spdk_bdev_part_free(&mut (*ctx).part)
+    }
+}
+
+extern "C" fn submit_request<V: VBdev>(ch: *mut spdk_io_channel, bio: *mut spdk_bdev_io) {
+    let channel = PartChannel {
+        ptr: unsafe { spdk_io_channel_get_ctx(ch) } as *mut spdk_bdev_part_channel,
+    };
+    let ctx = unsafe { &*((*channel.ptr).part as *const PartCtx<V>) };
+    ctx.vbdev.submit_request(&channel, BdevIo { ptr: bio });
+}
+
+extern "C" fn io_type_supported<V: VBdev>(ctx: *mut c_void, io_type: spdk_bdev_io_type) -> bool {
+    let ctx = unsafe { &mut *(ctx as *mut PartCtx<V>) };
+    let base = BDev {
+        ptr: unsafe { spdk_bdev_part_get_base_bdev(&mut ctx.part) },
+    };
+    IoType::from_raw(io_type).is_some_and(|io_type| ctx.vbdev.io_type_supported(&base, io_type))
+}
+
+extern "C" fn get_io_channel(ctx: *mut c_void) -> *mut spdk_io_channel {
+    // the part is the io_device
+    unsafe { spdk_get_io_channel(ctx) }
+}
+
+extern "C" fn dump_info_json<V: VBdev>(ctx: *mut c_void, w: *mut spdk_json_write_ctx) -> c_int {
+    let ctx = unsafe { &mut *(ctx as *mut PartCtx<V>) };
+    let base = BDev {
+        ptr: unsafe { spdk_bdev_part_get_base_bdev(&mut ctx.part) },
+    };
+    let offset_blocks = unsafe { spdk_bdev_part_get_offset_blocks(&mut ctx.part) };
+    JsonWriter { ptr: w }.named_object(V::NAME, |w| {
+        w.named_string("base_bdev", &base.name());
+        w.named_u64("offset_blocks", offset_blocks);
+        ctx.vbdev.dump_info_json(w);
+    });
+    0
+}